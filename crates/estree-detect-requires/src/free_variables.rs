@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use easter::decl::{Decl, Dtor};
+use easter::expr::Expr;
+use easter::fun::{Fun, Params};
+use easter::id::Id;
+use easter::patt::Patt;
+use easter::stmt::{ForHead, ForInHead, ForOfHead, Script, Stmt};
+use walk::Callbacks;
+
+/// The names declared in a function, and the names it uses that were not resolved yet.
+#[derive(Default)]
+struct Scope {
+    declared: HashSet<String>,
+    references: Vec<String>,
+}
+
+/// A tree walker that tracks variables that are used but never declared.
+///
+/// Declarations are hoisted to the top of their function, including `let` and `const`.
+pub struct FindFreeVariables {
+    /// The scopes we are currently inside of, innermost last.
+    scopes: Vec<Scope>,
+    free: Vec<String>,
+}
+
+impl FindFreeVariables {
+    pub fn new() -> FindFreeVariables {
+        FindFreeVariables {
+            scopes: vec![],
+            free: vec![],
+        }
+    }
+
+    pub fn get_free_variables(self) -> Vec<String> {
+        self.free
+    }
+
+    fn declare(&mut self, id: &Id) -> () {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.declared.insert(id.name.as_ref().to_string());
+        }
+    }
+
+    fn declare_patt(&mut self, patt: &Patt<Id>) -> () {
+        if let Patt::Simple(ref id) = *patt {
+            self.declare(id);
+        }
+    }
+
+    fn declare_dtors(&mut self, dtors: &[Dtor]) -> () {
+        for dtor in dtors {
+            if let Dtor::Simple(_, ref id, _) = *dtor {
+                self.declare(id);
+            }
+        }
+    }
+
+    /// Enter a function scope that declares `params`.
+    fn push_params(&mut self, params: &Params) -> () {
+        self.scopes.push(Scope::default());
+        for param in &params.list {
+            self.declare_patt(param);
+        }
+    }
+
+    /// Leave a function scope, passing its unresolved references on to the parent scope.
+    fn pop_params(&mut self) -> () {
+        let unresolved = self.pop_scope();
+        if let Some(parent) = self.scopes.last_mut() {
+            parent.references.extend(unresolved);
+        }
+    }
+
+    /// Leave a scope, and return the references that it did not declare.
+    fn pop_scope(&mut self) -> Vec<String> {
+        let scope = self.scopes.pop().unwrap();
+        scope.references.into_iter()
+            .filter(|name| !scope.declared.contains(name))
+            .collect()
+    }
+}
+
+impl Callbacks for FindFreeVariables {
+    fn pre_script(&mut self, _node: &Script) -> () {
+        self.scopes.push(Scope::default());
+    }
+
+    fn post_script(&mut self, _node: &Script) -> () {
+        for name in self.pop_scope() {
+            if !self.free.contains(&name) {
+                self.free.push(name);
+            }
+        }
+    }
+
+    fn pre_stmt(&mut self, stmt: &Stmt) -> () {
+        match *stmt {
+            Stmt::Var(_, ref dtors, _) => self.declare_dtors(dtors),
+            // for (var i = 0; ...)
+            Stmt::For(_, Some(ref head), ..) => match **head {
+                ForHead::Var(_, ref dtors) | ForHead::Let(_, ref dtors) => self.declare_dtors(dtors),
+                ForHead::Expr(..) => (),
+            },
+            // for (var key in object)
+            Stmt::ForIn(_, ref head, ..) => match **head {
+                ForInHead::VarInit(_, ref id, _) => self.declare(id),
+                ForInHead::Var(_, ref patt) | ForInHead::Let(_, ref patt) => self.declare_patt(patt),
+                ForInHead::Expr(_) => (),
+            },
+            Stmt::ForOf(_, ref head, ..) => match **head {
+                ForOfHead::Var(_, ref patt) | ForOfHead::Let(_, ref patt) => self.declare_patt(patt),
+                ForOfHead::Expr(_) => (),
+            },
+            _ => (),
+        }
+    }
+
+    fn pre_decl(&mut self, decl: &Decl) -> () {
+        match *decl {
+            Decl::Fun(ref fun) => self.declare(&fun.id),
+            Decl::Let(_, ref dtors, _) => self.declare_dtors(dtors),
+            Decl::Const(_, ref dtors, _) => {
+                for dtor in dtors {
+                    self.declare_patt(&dtor.patt);
+                }
+            },
+        }
+    }
+
+    fn pre_fun<I>(&mut self, fun: &Fun<I>) -> () {
+        self.push_params(&fun.params);
+    }
+
+    fn post_fun<I>(&mut self, _fun: &Fun<I>) -> () {
+        self.pop_params();
+    }
+
+    fn pre_arrow(&mut self, params: &Params) -> () {
+        self.push_params(params);
+    }
+
+    fn post_arrow(&mut self, _params: &Params) -> () {
+        self.pop_params();
+    }
+
+    fn pre_expr(&mut self, expr: &Expr) -> () {
+        if let Expr::Id(ref id) = *expr {
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.references.push(id.name.as_ref().to_string());
+            }
+        }
+    }
+}
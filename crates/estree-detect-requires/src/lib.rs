@@ -1,12 +1,30 @@
 extern crate easter;
 
+mod free_variables;
 mod walk;
 
 use easter::expr::{Expr, ExprListItem};
 use easter::id::Id;
+use easter::punc::BinopTag;
 use easter::stmt::Script;
+use free_variables::FindFreeVariables;
 use walk::{Walker, Callbacks};
 
+/// A require() call with a computed argument, like `require('./locales/' + lang + '.json')`.
+///
+/// Only the static parts of the string concatenation are known; the dynamic part in between
+/// can be anything at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequireContext {
+    /// The static string before the dynamic part, eg. `./locales/`.
+    pub prefix: String,
+    /// The static string after the dynamic part, eg. `.json`.
+    pub suffix: String,
+    /// Whether the dynamic part is made of several expressions, like `a + '/' + b`, so it is
+    /// likely to contain a `/`.
+    pub nested: bool,
+}
+
 /// Find require() calls in an ESTree Script node (from the easter crate).
 ///
 /// # Examples
@@ -18,6 +36,7 @@ use walk::{Walker, Callbacks};
 /// let requires = detect(&script("var x = require('y')").unwrap());
 /// assert_eq!(requires, vec!["y"]);
 /// ```
+/// require() calls inside a `require.ensure()` callback are not included, see `detect_lazy`.
 pub fn detect(ast: &Script) -> Vec<String> {
     let walker = Walker::new(ast, FindRequires::new());
     let find = walker.walk();
@@ -25,30 +44,184 @@ pub fn detect(ast: &Script) -> Vec<String> {
     find.get_modules()
 }
 
+/// Find modules that are loaded lazily using `require.ensure()`.
+/// These are the modules listed in the first argument, and require() calls inside the callback.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::detect_lazy;
+///
+/// let lazy = detect_lazy(&script("require.ensure(['x'], function (require) { require('y') })").unwrap());
+/// assert_eq!(lazy, vec!["x", "y"]);
+/// ```
+pub fn detect_lazy(ast: &Script) -> Vec<String> {
+    let walker = Walker::new(ast, FindRequires::new());
+    let find = walker.walk();
+
+    find.get_lazy_modules()
+}
+
+/// Find require() calls with a computed argument that starts with a static string.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::{detect_contexts, RequireContext};
+///
+/// let contexts = detect_contexts(&script("require('./locales/' + lang + '.json')").unwrap());
+/// assert_eq!(contexts, vec![RequireContext { prefix: "./locales/".into(), suffix: ".json".into(), nested: false }]);
+/// ```
+pub fn detect_contexts(ast: &Script) -> Vec<RequireContext> {
+    let walker = Walker::new(ast, FindRequires::new());
+    let find = walker.walk();
+
+    find.get_contexts()
+}
+
+/// Find variables that are used, but not declared anywhere in scope, like `process` in
+/// `process.env.NODE_ENV`.
+///
+/// # Examples
+///
+/// ```rust
+/// use esprit::script;
+/// use estree_detect_requires::detect_free_variables;
+///
+/// let free = detect_free_variables(&script("var a = 1; function f(b) { return a + b + c }").unwrap());
+/// assert_eq!(free, vec!["c"]);
+/// ```
+pub fn detect_free_variables(ast: &Script) -> Vec<String> {
+    let walker = Walker::new(ast, FindFreeVariables::new());
+    let find = walker.walk();
+
+    find.get_free_variables()
+}
+
 /// A tree walker that tracks require() calls.
 struct FindRequires {
     modules: Vec<String>,
+    lazy_modules: Vec<String>,
+    contexts: Vec<RequireContext>,
+    /// How many `require.ensure()` calls we are currently inside of.
+    ensure_depth: usize,
 }
 
 impl FindRequires {
     pub fn new() -> FindRequires {
-        FindRequires { modules: vec![] }
+        FindRequires {
+            modules: vec![],
+            lazy_modules: vec![],
+            contexts: vec![],
+            ensure_depth: 0,
+        }
     }
     pub fn get_modules(self) -> Vec<String> {
         self.modules
     }
+    pub fn get_lazy_modules(self) -> Vec<String> {
+        self.lazy_modules
+    }
+    pub fn get_contexts(self) -> Vec<RequireContext> {
+        self.contexts
+    }
 }
 
 impl Callbacks for FindRequires {
     fn pre_expr(&mut self, expr: &Expr) -> () {
         if let Expr::Call(_, ref callee, ref args) = *expr {
-            if is_require_name(callee) {
-                if let Some(&ExprListItem::Expr(Expr::String(_, ref val))) = args.first() {
-                    self.modules.push(val.value.clone());
+            if is_require_ensure(callee) {
+                self.ensure_depth += 1;
+                if let Some(&ExprListItem::Expr(Expr::Arr(_, ref elements))) = args.first() {
+                    for el in elements {
+                        if let Some(ExprListItem::Expr(Expr::String(_, ref val))) = *el {
+                            self.lazy_modules.push(val.value.clone());
+                        }
+                    }
+                }
+            } else if is_require_name(callee) {
+                match args.first() {
+                    Some(&ExprListItem::Expr(Expr::String(_, ref val))) =>
+                        if self.ensure_depth > 0 {
+                            self.lazy_modules.push(val.value.clone());
+                        } else {
+                            self.modules.push(val.value.clone());
+                        },
+                    Some(&ExprListItem::Expr(ref arg)) =>
+                        if let Some(context) = to_context(arg) {
+                            self.contexts.push(context);
+                        },
+                    _ => (),
                 }
             }
         }
     }
+
+    fn post_expr(&mut self, expr: &Expr) -> () {
+        if let Expr::Call(_, ref callee, _) = *expr {
+            if is_require_ensure(callee) {
+                self.ensure_depth -= 1;
+            }
+        }
+    }
+}
+
+/// Part of a string concatenation expression.
+enum ConcatPart {
+    Static(String),
+    Dynamic,
+}
+
+/// Flatten a chain of `+` expressions into its parts, eg. `'a' + b + 'c'` becomes
+/// `[Static("a"), Dynamic, Static("c")]`.
+fn flatten_concat(expr: &Expr, parts: &mut Vec<ConcatPart>) -> () {
+    match *expr {
+        Expr::Binop(_, ref op, ref left, ref right) if op.tag == BinopTag::Plus => {
+            flatten_concat(left.as_ref(), parts);
+            flatten_concat(right.as_ref(), parts);
+        },
+        Expr::String(_, ref val) => parts.push(ConcatPart::Static(val.value.clone())),
+        _ => parts.push(ConcatPart::Dynamic),
+    }
+}
+
+/// Turn a computed require() argument into a RequireContext.
+/// Returns `None` if the argument does not start with a static string, because then we
+/// would have no idea where to look for matching files.
+fn to_context(arg: &Expr) -> Option<RequireContext> {
+    let mut parts = vec![];
+    flatten_concat(arg, &mut parts);
+
+    let prefix_len = parts.iter().take_while(|part| match **part {
+        ConcatPart::Static(_) => true,
+        ConcatPart::Dynamic => false,
+    }).count();
+    // No dynamic part at all, or no static prefix.
+    if prefix_len == parts.len() || prefix_len == 0 {
+        return None
+    }
+    let suffix_len = parts.iter().rev().take_while(|part| match **part {
+        ConcatPart::Static(_) => true,
+        ConcatPart::Dynamic => false,
+    }).count();
+
+    let join = |parts: &[ConcatPart]| parts.iter().map(|part| match *part {
+        ConcatPart::Static(ref value) => value.as_str(),
+        ConcatPart::Dynamic => "",
+    }).collect::<String>();
+
+    let num_dynamic = parts.iter().filter(|part| match **part {
+        ConcatPart::Static(_) => false,
+        ConcatPart::Dynamic => true,
+    }).count();
+
+    Some(RequireContext {
+        prefix: join(&parts[..prefix_len]),
+        suffix: join(&parts[parts.len() - suffix_len..]),
+        nested: num_dynamic > 1,
+    })
 }
 
 fn is_require_name(id: &Expr) -> bool {
@@ -59,11 +232,19 @@ fn is_require_name(id: &Expr) -> bool {
     }
 }
 
+fn is_require_ensure(callee: &Expr) -> bool {
+    if let Expr::Dot(_, ref object, ref key) = *callee {
+        is_require_name(object.as_ref()) && key.value == "ensure"
+    } else {
+        false
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate esprit;
     use self::esprit::script;
-    use ::detect;
+    use ::{detect, detect_contexts, detect_free_variables, detect_lazy, RequireContext};
 
     #[test]
     fn detects_var_require() {
@@ -96,4 +277,57 @@ mod tests {
     fn detects_require_in_member_expression_object() {
         assert_eq!(detect(&script("require('util').inherits").unwrap()), vec!["util"]);
     }
+
+    #[test]
+    fn detects_require_context() {
+        assert_eq!(detect_contexts(&script("require('./locales/' + lang + '.json')").unwrap()), vec![
+            RequireContext { prefix: "./locales/".into(), suffix: ".json".into(), nested: false },
+        ]);
+        assert_eq!(detect_contexts(&script("require('./a/' + 'b/' + x)").unwrap()), vec![
+            RequireContext { prefix: "./a/b/".into(), suffix: "".into(), nested: false },
+        ]);
+        assert_eq!(detect_contexts(&script("require('./a/' + x + '/' + y + '.js')").unwrap()), vec![
+            RequireContext { prefix: "./a/".into(), suffix: ".js".into(), nested: true },
+        ]);
+    }
+
+    #[test]
+    fn ignores_require_context_without_prefix() {
+        assert_eq!(detect_contexts(&script("require(x + '.json'); require(x)").unwrap()), vec![]);
+        assert_eq!(detect(&script("require('./locales/' + lang)").unwrap()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn detects_require_ensure() {
+        let source = "
+            require('a')
+            require.ensure(['b', 'c'], function (require) {
+                require('b')
+                require('d')
+            })
+        ";
+        assert_eq!(detect(&script(source).unwrap()), vec!["a"]);
+        assert_eq!(detect_lazy(&script(source).unwrap()), vec!["b", "c", "b", "d"]);
+    }
+
+    #[test]
+    fn detects_free_variables() {
+        let source = "
+            var Buffer = require('buffer').Buffer
+            function f(global) {
+                return process.env.NODE_ENV + __dirname + global
+            }
+            g(Buffer, __dirname)
+            function g() {}
+        ";
+        assert_eq!(detect_free_variables(&script(source).unwrap()), vec!["require", "process", "__dirname"]);
+
+        assert_eq!(detect_free_variables(&script("var b = new Buffer(size)").unwrap()), vec!["Buffer", "size"]);
+        assert_eq!(detect_free_variables(&script("var f = (x) => x + global.y; var g = () => { return process }").unwrap()),
+                   vec!["global", "process"]);
+        assert_eq!(detect_free_variables(&script("var { env } = process; f({ Buffer })").unwrap()),
+                   vec!["process", "f", "Buffer"]);
+        assert_eq!(detect_free_variables(&script("for (var i = process.argv.length; i--;) {} for (var k in global) {}").unwrap()),
+                   vec!["process", "global"]);
+    }
 }
@@ -1,11 +1,11 @@
 extern crate easter;
 
-use easter::stmt::{Script, StmtListItem, Stmt};
+use easter::stmt::{Script, StmtListItem, Stmt, ForHead, ForInHead, ForOfHead};
 use easter::decl::{Decl, Dtor};
 use easter::expr::{ExprListItem, Expr};
 use easter::patt::{Patt, AssignTarget};
 use easter::obj::{Prop, PropVal};
-use easter::fun::Fun;
+use easter::fun::{ArrowBody, Fun, Params};
 
 /// An estree (easter crate) JavaScript AST walker.
 pub struct Walker<'a, C: Callbacks> {
@@ -27,6 +27,8 @@ pub trait Callbacks {
     fn pre_decl(&mut self, _node: &Decl) -> () {}
     /// Called before a Function node is entered.
     fn pre_fun<Id>(&mut self, _node: &Fun<Id>) -> () {}
+    /// Called before the body of an arrow function is entered.
+    fn pre_arrow(&mut self, _params: &Params) -> () {}
     /// Called after a top-level Script node was handled.
     fn post_script(&mut self, _node: &Script) -> () {}
     /// Called after a Statement node was handled.
@@ -37,6 +39,8 @@ pub trait Callbacks {
     fn post_decl(&mut self, _node: &Decl) -> () {}
     /// Called after a Function node was handled.
     fn post_fun<Id>(&mut self, _node: &Fun<Id>) -> () {}
+    /// Called after the body of an arrow function was handled.
+    fn post_arrow(&mut self, _params: &Params) -> () {}
 }
 
 impl<'a, C: Callbacks> Walker<'a, C> {
@@ -117,19 +121,19 @@ impl<'a, C: Callbacks> Walker<'a, C> {
                 self.walk_stmt(body.as_ref());
                 self.walk_expr(cond);
             },
-            Stmt::For(_, ref _init, ref cond, ref update, ref body) => {
-                // if let Some(ref node) = *head { self.walk_for_head(node); }
+            Stmt::For(_, ref head, ref cond, ref update, ref body) => {
+                if let Some(ref node) = *head { self.walk_for_head(node); }
                 if let Some(ref node) = *cond { self.walk_expr(node); }
                 if let Some(ref node) = *update { self.walk_expr(node); }
                 self.walk_stmt(body.as_ref());
             },
-            Stmt::ForIn(_, ref _head, ref iterable, ref body) => {
-                // if let Some(ref node) = *head { self.walk_for_in_head(node); }
+            Stmt::ForIn(_, ref head, ref iterable, ref body) => {
+                self.walk_for_in_head(head);
                 self.walk_expr(iterable);
                 self.walk_stmt(body.as_ref());
             },
-            Stmt::ForOf(_, ref _head, ref iterable, ref body) => {
-                // if let Some(ref node) = *head { self.walk_for_of_head(node); }
+            Stmt::ForOf(_, ref head, ref iterable, ref body) => {
+                self.walk_for_of_head(head);
                 self.walk_expr(iterable);
                 self.walk_stmt(body.as_ref());
             },
@@ -166,8 +170,35 @@ impl<'a, C: Callbacks> Walker<'a, C> {
     }
 
     fn walk_dtor(&mut self, dtor: &Dtor) -> () {
-        if let Dtor::Simple(_, _, Some(ref expr)) = *dtor {
-            self.walk_expr(expr);
+        match *dtor {
+            Dtor::Simple(_, _, Some(ref expr)) => self.walk_expr(expr),
+            // var { env } = process
+            Dtor::Compound(_, _, ref expr) => self.walk_expr(expr),
+            Dtor::Simple(_, _, None) => (),
+        }
+    }
+
+    /// Walk the initialisation part of a for(;;) loop.
+    fn walk_for_head(&mut self, head: &ForHead) -> () {
+        match *head {
+            ForHead::Var(_, ref dtors) | ForHead::Let(_, ref dtors) => self.walk_var(dtors),
+            ForHead::Expr(_, ref expr) => self.walk_expr(expr),
+        }
+    }
+
+    /// Walk the left-hand side of a for-in loop.
+    fn walk_for_in_head(&mut self, head: &ForInHead) -> () {
+        match *head {
+            ForInHead::VarInit(_, _, ref expr) | ForInHead::Expr(ref expr) => self.walk_expr(expr),
+            ForInHead::Var(_, ref patt) | ForInHead::Let(_, ref patt) => self.walk_patt(patt),
+        }
+    }
+
+    /// Walk the left-hand side of a for-of loop.
+    fn walk_for_of_head(&mut self, head: &ForOfHead) -> () {
+        match *head {
+            ForOfHead::Expr(ref expr) => self.walk_expr(expr),
+            ForOfHead::Var(_, ref patt) | ForOfHead::Let(_, ref patt) => self.walk_patt(patt),
         }
     }
 
@@ -180,12 +211,19 @@ impl<'a, C: Callbacks> Walker<'a, C> {
             Expr::Call(_, ref callee, ref args) => {
                 self.walk_expr(callee);
                 for arg in args {
-                    match *arg {
-                        ExprListItem::Expr(ref node) => self.walk_expr(node),
-                        ExprListItem::Spread(_, ref node) => self.walk_expr(node),
+                    self.walk_expr_list_item(arg);
+                }
+            },
+            // new Buffer(n), new Foo
+            Expr::New(_, ref callee, ref args) => {
+                self.walk_expr(callee);
+                if let Some(ref args) = *args {
+                    for arg in args {
+                        self.walk_expr_list_item(arg);
                     }
                 }
             },
+            Expr::Arrow(_, ref params, ref body) => self.walk_arrow(params, body),
             Expr::Seq(_, ref exprs) => {
                 for expr in exprs {
                     self.walk_expr(expr);
@@ -238,6 +276,27 @@ impl<'a, C: Callbacks> Walker<'a, C> {
         self.callbacks.post_expr(expr);
     }
 
+    fn walk_expr_list_item(&mut self, item: &ExprListItem) -> () {
+        match *item {
+            ExprListItem::Expr(ref node) => self.walk_expr(node),
+            ExprListItem::Spread(_, ref node) => self.walk_expr(node),
+        }
+    }
+
+    /// Walk the body of an arrow function.
+    fn walk_arrow(&mut self, params: &Params, body: &ArrowBody) -> () {
+        self.callbacks.pre_arrow(params);
+        match *body {
+            ArrowBody::Expr(ref expr) => self.walk_expr(expr),
+            ArrowBody::Body(ref block) => {
+                for item in &block.items {
+                    self.walk_stmt_item(item);
+                }
+            },
+        }
+        self.callbacks.post_arrow(params);
+    }
+
     /// Walk a function declaration or expression node.
     fn walk_fun<Id>(&mut self, fun: &Fun<Id>) -> () {
         self.callbacks.pre_fun(fun);
@@ -275,7 +334,8 @@ impl<'a, C: Callbacks> Walker<'a, C> {
                 }
             },
             Prop::Method(ref fun) => self.walk_fun(fun),
-            Prop::Shorthand(ref id) => (),
+            // { Buffer } refers to the variable Buffer.
+            Prop::Shorthand(ref id) => self.walk_expr(&Expr::Id(id.clone())),
         }
     }
 }
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::vec::IntoIter;
+use futures::{Future, future};
+use futures::future::{Loop, join_all, loop_fn};
+use futures_cpupool::CpuPool;
+use bundler::{Bundle, PackOptions, check_entries};
+use deps::Deps;
+use error::{Error, Result};
+use graph::ModuleRecord;
+
+/// A bundle that is being built on a thread pool, see `Bundler::build_async`.
+pub type BuildFuture = Box<Future<Item = Bundle, Error = Error> + Send>;
+
+type DepsFuture<T> = Box<Future<Item = T, Error = Error> + Send>;
+
+/// The state between waves of loaded files: the graph, the next wave, and the records that
+/// still have to be linked.
+type Wave = (Deps, Vec<PathBuf>, Vec<ModuleRecord>);
+
+pub(crate) fn build_async(entries: Vec<String>, deps: Deps, options: PackOptions, pool: &CpuPool) -> BuildFuture {
+    if let Err(err) = check_entries(&entries, &deps) {
+        return Box::new(future::err(err))
+    }
+
+    let entry_pool = pool.clone();
+    let graph = loop_fn((deps, entries.into_iter()), move |(deps, mut entries): (Deps, IntoIter<String>)| -> DepsFuture<Loop<Deps, (Deps, IntoIter<String>)>> {
+        match entries.next() {
+            Some(entry) => Box::new(run_entry(deps, entry, entry_pool.clone())
+                .map(move |deps| Loop::Continue((deps, entries)))),
+            None => Box::new(future::ok(Loop::Break(deps))),
+        }
+    });
+
+    let pack_pool = pool.clone();
+    Box::new(graph.and_then(move |graph| pack_pool.spawn_fn(move || Ok::<Bundle, Error>(options.pack(graph)))))
+}
+
+/// Load an entry point and its dependencies, like `Deps::run`.
+/// Every file in a wave is loaded as a separate task on the pool.
+fn run_entry(deps: Deps, entry: String, pool: CpuPool) -> DepsFuture<Deps> {
+    let loader = deps.loader();
+    let resolved = pool.spawn_fn(move || loader.resolve_entry(&entry));
+
+    Box::new(resolved.and_then(move |resolved| {
+        let mut deps = deps;
+        let queue = deps.start(resolved);
+        loop_fn((deps, queue, vec![]), move |(mut deps, queue, mut unlinked): Wave| -> DepsFuture<Loop<Deps, Wave>> {
+            if queue.is_empty() {
+                deps.link(unlinked);
+                return Box::new(future::ok(Loop::Break(deps)))
+            }
+
+            let loader = deps.loader();
+            let wave = queue.into_iter()
+                .map(|path| {
+                    let loader = Arc::clone(&loader);
+                    pool.spawn_fn(move || loader.load_file(path))
+                })
+                .collect::<Vec<_>>();
+            // If a file fails to load, the rest of the wave is cancelled.
+            Box::new(join_all(wave).and_then(move |loaded| -> Result<Loop<Deps, Wave>> {
+                let loaded = loaded.into_iter().map(Ok).collect();
+                let next_queue = deps.add_loaded(loaded, &mut unlinked)?;
+                Ok(Loop::Continue((deps, next_queue, unlinked)))
+            }))
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use futures::future::join_all;
+    use futures_cpupool::CpuPool;
+    use bundler::{Bundler, Output};
+
+    fn source(output: Output) -> String {
+        match output {
+            Output::Single(source) => source,
+            _ => panic!("expected a single bundle"),
+        }
+    }
+
+    #[test]
+    fn builds_concurrently() {
+        let pool = CpuPool::new(4);
+        let builds = vec![
+            Bundler::new().with_entry("./tests/fixtures/module-exports/index.js").build_async(&pool),
+            Bundler::new().with_entry("./tests/fixtures/cycle/a.js").build_async(&pool),
+        ];
+        let bundles = join_all(builds).wait().unwrap();
+
+        let expected = Bundler::new().with_entry("./tests/fixtures/module-exports/index.js").build().unwrap();
+        let mut bundles = bundles.into_iter();
+        assert_eq!(source(bundles.next().unwrap().output), source(expected.output));
+        assert_eq!(bundles.next().unwrap().graph.len(), 2);
+    }
+}
@@ -1,13 +1,15 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use node_resolve::{Resolver, is_core_module};
-use quicli::prelude::Result;
+use error::{Error, Result};
 use node_core_shims::{NodeBuiltin, get_builtin_mapping};
 
 /// Map builtin module names to a resolvable module ID.
-pub trait Builtins {
+/// Implementations are shared between the threads that load files.
+pub trait Builtins: Send + Sync {
     fn is_builtin(&self, module_id: &str) -> bool;
-    fn resolve(&self, resolver: &Resolver, module_id: &str) -> Result<Option<PathBuf>>;
+    /// Resolve a builtin module that is required by the file at `from`.
+    fn resolve(&self, resolver: &Resolver, module_id: &str, from: &Path) -> Result<Option<PathBuf>>;
 }
 
 /// Support Node builtins.
@@ -30,9 +32,9 @@ impl Builtins for NodeBuiltins {
         is_core_module(module_id)
     }
 
-    fn resolve(&self, resolver: &Resolver, module_id: &str) -> Result<Option<PathBuf>> {
+    fn resolve(&self, resolver: &Resolver, module_id: &str, from: &Path) -> Result<Option<PathBuf>> {
         let builtin: &NodeBuiltin = self.mapping.get(module_id)
-            .unwrap_or_else(|| panic!("Missing builtin mapping for {}", module_id));
+            .ok_or_else(|| Error::MissingBuiltin(module_id.to_string()))?;
 
         match *builtin {
             NodeBuiltin::Package(ref package_id) => {
@@ -40,7 +42,7 @@ impl Builtins for NodeBuiltins {
                     .with_basedir(self.basedir.clone())
                     .resolve(package_id)
                     .map(|r| Some(r))
-                    .map_err(|e| e.into())
+                    .map_err(|e| Error::resolve(package_id, Some(from), e))
             },
             NodeBuiltin::Stub => Ok(None),
         }
@@ -51,5 +53,5 @@ impl Builtins for NodeBuiltins {
 pub struct NoBuiltins;
 impl Builtins for NoBuiltins {
     fn is_builtin(&self, _module_id: &str) -> bool { false }
-    fn resolve(&self, _resolver: &Resolver, _module_id: &str) -> Result<Option<PathBuf>> { Ok(None) }
+    fn resolve(&self, _resolver: &Resolver, _module_id: &str, _from: &Path) -> Result<Option<PathBuf>> { Ok(None) }
 }
@@ -0,0 +1,272 @@
+use std::path::PathBuf;
+use futures_cpupool::CpuPool;
+use node_resolve::Resolver;
+use async_build::{BuildFuture, build_async};
+use cache::Cache;
+use deps::Deps;
+use error::{Error, Result};
+use events::{Event, Listener, Listeners};
+use graph::ModuleMap;
+use pack::{FactoredBundle, IdStrategy, Pack, SplitBundle, VendorBundle};
+use transform::TransformRule;
+
+/// The kind of output to generate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputKind {
+    /// A single bundle with every module.
+    Single,
+    /// A main bundle, and chunks for modules that are loaded with `require.ensure()`.
+    Chunks,
+    /// A bundle for each entry point, and a common bundle for modules shared between them.
+    Factored,
+    /// A bundle for modules from `node_modules`, and a bundle for all other modules.
+    Vendor,
+}
+
+/// The generated bundle files.
+pub enum Output {
+    Single(String),
+    Chunks(SplitBundle),
+    Factored(FactoredBundle),
+    Vendor(VendorBundle),
+}
+
+/// The result of a build.
+pub struct Bundle {
+    /// The module graph that was bundled.
+    pub graph: Deps,
+    pub output: Output,
+    /// Number of bytes saved by deduplicating identical modules.
+    pub dedupe_savings: usize,
+}
+
+/// Build a bundle from a list of entry points.
+///
+/// # Examples
+///
+/// ```no_run
+/// use js_bundler::{Bundler, Output};
+///
+/// let bundle = Bundler::new()
+///     .with_entry("./src/app.js")
+///     .with_public_path("/assets/".into())
+///     .build()
+///     .unwrap();
+/// if let Output::Single(source) = bundle.output {
+///     println!("{}", source);
+/// }
+/// ```
+pub struct Bundler {
+    entries: Vec<String>,
+    deps: Deps,
+    options: PackOptions,
+}
+
+/// Options for generating the bundle once the module graph is done.
+pub(crate) struct PackOptions {
+    output: OutputKind,
+    public_path: String,
+    root: Option<PathBuf>,
+    dedupe: bool,
+    id_strategy: IdStrategy,
+    dependency_order: bool,
+}
+
+impl Bundler {
+    pub fn new() -> Bundler {
+        Bundler {
+            entries: vec![],
+            deps: Deps::new(),
+            options: PackOptions {
+                output: OutputKind::Single,
+                public_path: String::new(),
+                root: None,
+                dedupe: true,
+                id_strategy: IdStrategy::Sequential,
+                dependency_order: false,
+            },
+        }
+    }
+
+    /// Add an entry point.
+    pub fn with_entry(mut self, entry: &str) -> Self {
+        self.entries.push(entry.to_string());
+        self
+    }
+
+    /// Add several entry points.
+    pub fn with_entries(mut self, entries: &[String]) -> Self {
+        self.entries.extend(entries.iter().cloned());
+        self
+    }
+
+    /// Use a different resolver. See `Deps::with_resolver`.
+    pub fn with_resolver(mut self, resolver: Resolver) -> Self {
+        self.deps = self.deps.with_resolver(resolver);
+        self
+    }
+
+    /// Configure the base path for Node builtin shims resolution. See `Deps::with_builtins_path`.
+    pub fn with_builtins_path(mut self, path: PathBuf) -> Self {
+        self.deps = self.deps.with_builtins_path(path);
+        self
+    }
+
+    /// Toggle inclusion of builtins. See `Deps::include_builtins`.
+    pub fn include_builtins(mut self, include: bool) -> Self {
+        self.deps = self.deps.include_builtins(include);
+        self
+    }
+
+    /// Store loaded files in a cache directory. See `Deps::with_cache`.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.deps = self.deps.with_cache(cache);
+        self
+    }
+
+    /// Start from modules that were loaded before. See `Deps::with_modules`.
+    pub fn with_modules(mut self, modules: ModuleMap) -> Self {
+        self.deps = self.deps.with_modules(modules);
+        self
+    }
+
+    /// Apply a transform to loaded files. See `Deps::with_transform`.
+    pub fn with_transform(mut self, rule: TransformRule) -> Self {
+        self.deps = self.deps.with_transform(rule);
+        self
+    }
+
+    /// Send build events to a listener. See `Deps::with_listener`.
+    pub fn with_listener<L: Listener + 'static>(mut self, listener: L) -> Self {
+        self.deps = self.deps.with_listener(listener);
+        self
+    }
+
+    /// Set the kind of output to generate. Defaults to `OutputKind::Single`.
+    pub fn with_output(mut self, output: OutputKind) -> Self {
+        self.options.output = output;
+        self
+    }
+
+    /// Set the URL that chunk files are loaded from at runtime. See `Pack::with_public_path`.
+    pub fn with_public_path(mut self, public_path: String) -> Self {
+        self.options.public_path = public_path;
+        self
+    }
+
+    /// Set the project root directory. See `Pack::with_root`.
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.options.root = Some(root);
+        self
+    }
+
+    /// Toggle deduplication of modules with identical source code. See `Pack::dedupe`.
+    pub fn dedupe(mut self, dedupe: bool) -> Self {
+        self.options.dedupe = dedupe;
+        self
+    }
+
+    /// Set how module IDs are generated. See `Pack::with_ids`.
+    pub fn with_ids(mut self, id_strategy: IdStrategy) -> Self {
+        self.options.id_strategy = id_strategy;
+        self
+    }
+
+    /// Toggle writing modules in dependency order. See `Pack::dependency_order`.
+    pub fn dependency_order(mut self, dependency_order: bool) -> Self {
+        self.options.dependency_order = dependency_order;
+        self
+    }
+
+    /// Load all entry points and their dependencies, without generating a bundle.
+    pub fn graph(self) -> Result<Deps> {
+        load_graph(&self.entries, self.deps)
+    }
+
+    /// Load all entry points and their dependencies, and generate the bundle.
+    pub fn build(self) -> Result<Bundle> {
+        let graph = load_graph(&self.entries, self.deps)?;
+        Ok(self.options.pack(graph))
+    }
+
+    /// Build the bundle on a thread pool, without blocking the current thread.
+    ///
+    /// Files are read, parsed and resolved as separate tasks on the pool, so many builds can
+    /// share one pool. Dropping the returned future cancels the build: files that were not
+    /// loaded yet will not be loaded.
+    pub fn build_async(self, pool: &CpuPool) -> BuildFuture {
+        build_async(self.entries, self.deps, self.options, pool)
+    }
+}
+
+impl PackOptions {
+    /// Generate the bundle for a finished module graph.
+    pub(crate) fn pack(self, graph: Deps) -> Bundle {
+        let (output, dedupe_savings) = {
+            let mut pack = Pack::new(&graph)
+                .with_public_path(self.public_path)
+                .dedupe(self.dedupe)
+                .with_ids(self.id_strategy)
+                .dependency_order(self.dependency_order);
+            if let Some(root) = self.root {
+                pack = pack.with_root(root);
+            }
+            let dedupe_savings = if self.dedupe { pack.dedupe_savings() } else { 0 };
+            let output = match self.output {
+                OutputKind::Single => Output::Single(pack.to_string()),
+                OutputKind::Chunks => Output::Chunks(pack.to_split_bundle()),
+                OutputKind::Factored => Output::Factored(pack.to_factored()),
+                OutputKind::Vendor => Output::Vendor(pack.to_vendor_split()),
+            };
+            (output, dedupe_savings)
+        };
+        output.emit_generated(graph.listeners());
+        Bundle { graph, output, dedupe_savings }
+    }
+}
+
+impl Output {
+    /// Emit a `BundleGenerated` event for each generated file.
+    fn emit_generated(&self, listeners: &Listeners) -> () {
+        let generated = |filename: Option<&str>, source: &str| {
+            listeners.emit(Event::BundleGenerated { filename, size: source.len() });
+        };
+        match *self {
+            Output::Single(ref source) => generated(None, source),
+            Output::Chunks(ref split) => {
+                generated(None, &split.main);
+                for chunk in &split.chunks {
+                    generated(Some(&chunk.filename), &chunk.source);
+                }
+                generated(Some("manifest.json"), &split.manifest);
+            },
+            Output::Factored(ref factored) => {
+                generated(Some("common.js"), &factored.common);
+                for entry in &factored.entries {
+                    generated(Some(&entry.filename), &entry.source);
+                }
+            },
+            Output::Vendor(ref split) => {
+                generated(Some("vendor.js"), &split.vendor);
+                generated(Some("app.js"), &split.app);
+            },
+        }
+    }
+}
+
+/// Check that there is something to bundle.
+pub(crate) fn check_entries(entries: &[String], deps: &Deps) -> Result<()> {
+    if entries.is_empty() && deps.is_empty() {
+        Err(Error::InvalidInput("Expected at least one entry point".into()))
+    } else {
+        Ok(())
+    }
+}
+
+fn load_graph(entries: &[String], mut deps: Deps) -> Result<Deps> {
+    check_entries(entries, &deps)?;
+    for entry in entries {
+        deps.run(entry)?;
+    }
+    Ok(deps)
+}
@@ -0,0 +1,170 @@
+use std::fs::{File, create_dir_all};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use serde_json;
+use sha1::{Sha1, Digest};
+use estree_detect_requires::RequireContext;
+use error::{Error, Result};
+use graph::{Hash, SourceFile, hash_to_hex};
+
+/// Bump this when the format of cache entries changes, to ignore old entries.
+const CACHE_VERSION: u32 = 6;
+
+/// The options that change how a file is loaded. Cache entries are only used if these match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheOptions {
+    /// Whether builtin modules are included when resolving dependencies.
+    pub include_builtins: bool,
+    /// Whether `process` and `Buffer` are inserted into modules that use them. This also
+    /// depends on whether there are shims for them, see `FileLoader::load_file`.
+    pub insert_builtin_globals: bool,
+    /// Whether the file is an ES module.
+    pub is_module: bool,
+}
+
+/// Everything we know about a file after loading it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    version: u32,
+    /// Hex encoded hash of the original file contents.
+    hash: String,
+    /// Whether builtin modules were included when resolving dependencies.
+    include_builtins: bool,
+    /// Whether `process` and `Buffer` were inserted into modules that use them.
+    insert_builtin_globals: bool,
+    /// Whether the file was loaded as an ES module.
+    is_module: bool,
+    /// The transforms that were applied, see `Transforms::cache_key`.
+    transforms: String,
+    /// The transformed source code.
+    source: String,
+    dependencies: Vec<String>,
+    lazy_dependencies: Vec<String>,
+    /// Prefixes and suffixes of computed require() calls, and whether they are nested.
+    contexts: Vec<(String, String, bool)>,
+}
+
+impl CacheEntry {
+    /// Create a cache entry for a loaded file.
+    /// Returns `None` for files that we can't cache.
+    ///
+    /// Dependencies are not stored resolved, because a new file or package can change what
+    /// they resolve to without changing the file itself. Resolving them again is cheap
+    /// compared to parsing and transforming.
+    pub fn new(file: &SourceFile, options: CacheOptions, transforms: String) -> Option<CacheEntry> {
+        match *file {
+            SourceFile::CJS { ref source, ref hash, ref dependencies, ref lazy_dependencies, ref contexts, .. } =>
+                Some(CacheEntry {
+                    version: CACHE_VERSION,
+                    hash: hash_to_hex(hash),
+                    include_builtins: options.include_builtins,
+                    insert_builtin_globals: options.insert_builtin_globals,
+                    is_module: options.is_module,
+                    transforms,
+                    source: source.clone(),
+                    dependencies: dependencies.clone(),
+                    lazy_dependencies: lazy_dependencies.clone(),
+                    contexts: contexts.iter()
+                        .map(|context| (context.prefix.clone(), context.suffix.clone(), context.nested))
+                        .collect(),
+                }),
+            SourceFile::JSON { .. } => None,
+        }
+    }
+
+    fn options(&self) -> CacheOptions {
+        CacheOptions {
+            include_builtins: self.include_builtins,
+            insert_builtin_globals: self.insert_builtin_globals,
+            is_module: self.is_module,
+        }
+    }
+
+    /// Turn the cache entry back into a source file, without a syntax tree.
+    pub fn into_source_file(self, path: PathBuf, hash: Hash) -> SourceFile {
+        SourceFile::CJS {
+            path,
+            source: self.source,
+            hash,
+            ast: None,
+            dependencies: self.dependencies,
+            lazy_dependencies: self.lazy_dependencies,
+            contexts: self.contexts.into_iter()
+                .map(|(prefix, suffix, nested)| RequireContext { prefix, suffix, nested })
+                .collect(),
+        }
+    }
+}
+
+/// Stores loaded files in a directory, so they do not have to be parsed and transformed
+/// again if they did not change since the last build.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Use `dir` as the cache directory. It is created if it does not exist.
+    pub fn new(dir: PathBuf) -> Result<Cache> {
+        create_dir_all(&dir).map_err(|err| Error::io(&dir, err))?;
+        Ok(Cache { dir })
+    }
+
+    /// Get the cache entry for a file, if the file contents still match `hash` and it was
+    /// loaded with the same options.
+    pub fn get(&self, path: &Path, hash: &Hash, options: CacheOptions, transforms: &str) -> Option<CacheEntry> {
+        let file = File::open(self.entry_path(path)).ok()?;
+        let entry: CacheEntry = serde_json::from_reader(BufReader::new(file)).ok()?;
+        if entry.version == CACHE_VERSION && entry.hash == hash_to_hex(hash) && entry.options() == options
+            && entry.transforms == transforms {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Store the cache entry for a file.
+    pub fn put(&self, path: &Path, entry: &CacheEntry) -> Result<()> {
+        let entry_path = self.entry_path(path);
+        let file = File::create(&entry_path).map_err(|err| Error::io(&entry_path, err))?;
+        serde_json::to_writer(BufWriter::new(file), entry).map_err(|err| Error::json(&entry_path, err))?;
+        Ok(())
+    }
+
+    fn entry_path(&self, path: &Path) -> PathBuf {
+        let name = hash_to_hex(&Sha1::digest_str(&path.to_string_lossy()));
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+    use sha1::{Sha1, Digest};
+    use graph::SourceFile;
+    use super::{Cache, CacheEntry, CacheOptions};
+
+    #[test]
+    fn entries_are_only_used_with_the_same_options() {
+        let cache = Cache::new(env::temp_dir().join("js-bundler-test-cache-options")).unwrap();
+        let path = PathBuf::from("./src/app.js");
+        let hash = Sha1::digest_str("process.nextTick(f)");
+        let file = SourceFile::CJS {
+            path: path.clone(),
+            source: "process.nextTick(f)".to_string(),
+            hash: hash.clone(),
+            ast: None,
+            dependencies: vec![],
+            lazy_dependencies: vec![],
+            contexts: vec![],
+        };
+        // Loaded without shims for `process`, so it was not inserted.
+        let options = CacheOptions { include_builtins: true, insert_builtin_globals: false, is_module: false };
+        let entry = CacheEntry::new(&file, options, String::new()).unwrap();
+        cache.put(&path, &entry).unwrap();
+
+        assert!(cache.get(&path, &hash, options, "").is_some());
+        assert!(cache.get(&path, &hash, CacheOptions { insert_builtin_globals: true, ..options }, "").is_none());
+        assert!(cache.get(&path, &hash, CacheOptions { is_module: true, ..options }, "").is_none());
+    }
+}
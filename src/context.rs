@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use glob::{glob, Pattern};
+use estree_detect_requires::RequireContext;
+use error::{Error, Result};
+use events::Listeners;
+
+/// Find all files that a computed require() call may refer to.
+///
+/// Returns pairs of the module IDs that the require() call would receive at runtime, and
+/// the files they refer to. For `require('./locales/' + lang + '.json')`, that could be
+/// `("./locales/en.json", "/path/to/locales/en.json")`.
+///
+/// `from` is the file that contains the require() call. It is never included itself. If the
+/// suffix does not end in a file extension, only files with one of `extensions` are included,
+/// so things like README.md do not end up in the bundle.
+pub fn expand_context(from: &Path, context: &RequireContext, extensions: &[&str], listeners: &Listeners) -> Result<Vec<(String, PathBuf)>> {
+    let basedir = from.parent().unwrap();
+    let prefix = &context.prefix;
+    if !prefix.starts_with("./") && !prefix.starts_with("../") {
+        listeners.warn(&format!("Cannot include dynamic require('{}' + ...): only relative paths are supported", prefix));
+        return Ok(vec![])
+    }
+
+    // Split "./locales/en-" into "./locales/" and "en-".
+    let split_at = prefix.rfind('/').unwrap() + 1;
+    let (dir_part, name_part) = prefix.split_at(split_at);
+    let search_dir = basedir.join(dir_part);
+
+    let dir = Pattern::escape(&search_dir.to_string_lossy());
+    let name = Pattern::escape(name_part);
+    let suffix = Pattern::escape(&context.suffix);
+    let mut patterns = vec![format!("{}/{}*{}", dir, name, suffix)];
+    // Globs only match a single directory level, so if the dynamic part may contain a `/`,
+    // also look in nested directories.
+    if context.nested {
+        patterns.push(format!("{}/{}*/**/*{}", dir, name, suffix));
+    }
+
+    let has_extension = context.suffix.rsplit('/').next().map_or(false, |name| name.contains('.'));
+    let from = from.canonicalize().map_err(|err| Error::io(from, err))?;
+
+    let mut paths = BTreeSet::new();
+    for pattern in patterns {
+        let entries = glob(&pattern)
+            .map_err(|err| Error::InvalidInput(format!("Invalid pattern {}: {}", pattern, err)))?;
+        for entry in entries {
+            let path = entry.map_err(|err| Error::io(&err.path().to_path_buf(), err.into_error()))?;
+            if !path.is_file() || path.canonicalize().ok().as_ref() == Some(&from) {
+                continue
+            }
+            let name = path.to_string_lossy().into_owned();
+            if has_extension || extensions.iter().any(|ext| name.ends_with(ext)) {
+                paths.insert(path);
+            }
+        }
+    }
+
+    let mut matches = vec![];
+    for path in paths {
+        let relative = match path.strip_prefix(&search_dir) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+        let module_id = format!("{}{}", dir_part, relative);
+        // `require('./locales/' + lang)` should also find ./locales/en.json.
+        if context.suffix.is_empty() {
+            if let Some(ext) = path.extension() {
+                let without_ext = module_id[..module_id.len() - ext.len() - 1].to_string();
+                matches.push((without_ext, path.clone()));
+            }
+        }
+        matches.push((module_id, path));
+    }
+
+    if matches.is_empty() {
+        listeners.warn(&format!("Dynamic require('{}' + ... + '{}') in {} did not match any files",
+                                prefix, context.suffix, from.to_string_lossy()));
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use estree_detect_requires::RequireContext;
+    use events::Listeners;
+    use super::expand_context;
+
+    const EXTENSIONS: &[&str] = &[".js", ".json"];
+
+    #[test]
+    fn expands_nested_contexts() {
+        let basedir = Path::new("./tests/fixtures/context");
+        let from = basedir.join("index.js");
+        let context = RequireContext {
+            prefix: "./a/".to_string(),
+            suffix: ".js".to_string(),
+            nested: true,
+        };
+        let matches = expand_context(&from, &context, EXTENSIONS, &Listeners::new()).unwrap();
+        assert_eq!(matches, vec![
+            ("./a/x/one.js".to_string(), basedir.join("a/x/one.js")),
+            ("./a/y/two.js".to_string(), basedir.join("a/y/two.js")),
+        ]);
+
+        let flat = RequireContext { nested: false, ..context };
+        assert_eq!(expand_context(&from, &flat, EXTENSIONS, &Listeners::new()).unwrap(), Vec::<(String, PathBuf)>::new());
+    }
+    #[test]
+    fn only_includes_scripts_without_a_suffix() {
+        let from = Path::new("./tests/fixtures/context-extensions/index.js");
+        let context = RequireContext {
+            prefix: "./".to_string(),
+            suffix: String::new(),
+            nested: false,
+        };
+        let names = expand_context(from, &context, EXTENSIONS, &Listeners::new()).unwrap().into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["./one", "./one.js", "./two", "./two.json"]);
+    }
+}
@@ -1,40 +1,67 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
-use quicli::prelude::*; // TODO use `failure`?
+use std::sync::Arc;
 use node_resolve::Resolver;
+use rayon::prelude::*;
+use estree_detect_requires::RequireContext;
 use builtins::{Builtins, NodeBuiltins, NoBuiltins};
-use graph::{ModuleMap, Dependency, Dependencies, SourceFile, ModuleRecord};
+use cache::{Cache, CacheEntry, CacheOptions};
+use context::expand_context;
+use error::{Error, Result};
+use events::{Event, Listener, Listeners};
+use graph::{ModuleMap, Dependency, Dependencies, SourceFile, ModuleRecord, cycles as cycles_of};
 use loader::LoadFile;
+use package::Packages;
+use transform::{TransformRule, Transforms};
 
 /// Builds a dependency tree for Node modules.
 pub struct Deps {
     module_id: u32,
-    resolver: Resolver,
+    loader: Arc<FileLoader>,
     loaded_files: HashSet<PathBuf>,
     module_map: ModuleMap,
+}
+
+/// Loads files and resolves their dependencies.
+/// This is shared between the threads that load files.
+pub(crate) struct FileLoader {
+    resolver: Resolver,
     include_builtins: bool,
-    builtins: Box<Builtins>,
+    builtins: Box<Builtins + Send + Sync>,
+    cache: Option<Cache>,
+    /// Transforms that apply to every file.
+    transforms: Transforms,
+    packages: Packages,
+    listeners: Listeners,
 }
 
+/// Extensions of the files that we know how to load.
+const EXTENSIONS: &[&str] = &[".js", ".json"];
+
 impl Deps {
     /// Create a new dependency tree.
     pub fn new() -> Deps {
         let resolver = Resolver::new()
-            .with_extensions(&[".js", ".json"]);
+            .with_extensions(EXTENSIONS);
         let module_map = ModuleMap::new();
         let module_id = 0;
         let loaded_files = HashSet::new();
         let builtins = NoBuiltins;
 
         Deps {
-            resolver,
+            loader: Arc::new(FileLoader {
+                resolver,
+                include_builtins: true,
+                builtins: Box::new(builtins),
+                cache: None,
+                transforms: Transforms::new(),
+                packages: Packages::new(),
+                listeners: Listeners::new(),
+            }),
             module_map,
             module_id,
             loaded_files,
-            include_builtins: true,
-            builtins: Box::new(builtins),
         }
     }
 
@@ -43,14 +70,19 @@ impl Deps {
     /// # Examples
     ///
     /// ```
+    /// extern crate node_resolve;
+    /// extern crate js_bundler;
+    ///
     /// use node_resolve::Resolver;
-    /// use deps::Deps;
+    /// use js_bundler::deps::Deps;
     ///
+    /// # fn main() {
     /// let deps = Deps::new()
     ///     .with_resolver(Resolver::new().preserve_symlinks(false));
+    /// # }
     /// ```
     pub fn with_resolver(mut self, resolver: Resolver) -> Self {
-        self.resolver = resolver;
+        self.loader_mut().resolver = resolver;
         self
     }
 
@@ -59,19 +91,19 @@ impl Deps {
     /// # Examples
     ///
     /// ```
-    /// use deps::Deps;
+    /// use js_bundler::deps::Deps;
     /// // Use builtin shims provided by the node-libs-browser package.
     /// let deps = Deps::new()
-    ///     .with_builtins_path("./node_modules/node-libs-browser".into())
+    ///     .with_builtins_path("./node_modules/node-libs-browser".into());
     /// ```
     pub fn with_builtins_path(mut self, path: PathBuf) -> Self {
-        self.builtins = Box::new(NodeBuiltins::new(path));
+        self.loader_mut().builtins = Box::new(NodeBuiltins::new(path));
         self
     }
 
     /// Disable bundling builtin modules.
     pub fn no_builtins(mut self) -> Self {
-        self.builtins = Box::new(NoBuiltins);
+        self.loader_mut().builtins = Box::new(NoBuiltins);
         self
     }
 
@@ -81,84 +113,357 @@ impl Deps {
     /// modules.
     /// If `true`, shims for builtin modules will be included in the bundle.
     pub fn include_builtins(mut self, include: bool) -> Self {
-        self.include_builtins = include;
+        self.loader_mut().include_builtins = include;
+        self
+    }
+
+    /// Store loaded files in a cache directory. Files that did not change since the last
+    /// build are not parsed and transformed again, and their dependencies are not resolved
+    /// again as long as the resolved files still exist.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.loader_mut().cache = Some(cache);
+        self
+    }
+
+    /// Apply a transform to all loaded files, before they are parsed.
+    /// Transforms run in the order they were added.
+    ///
+    /// Packages can also declare transforms in the `browserify.transform` field of their
+    /// package.json. Those only apply to the package's own files, and run before the transforms
+    /// that are added here.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate glob;
+    /// # extern crate js_bundler;
+    /// # extern crate serde_json;
+    /// # use std::path::Path;
+    /// # use js_bundler::Result;
+    /// # use js_bundler::transform::Transform;
+    /// # struct Coffee;
+    /// # impl Transform for Coffee {
+    /// #     fn name(&self) -> &str { "coffee" }
+    /// #     fn transform(&self, _: &Path, source: String, _: &serde_json::Value) -> Result<String> { Ok(source) }
+    /// # }
+    /// # fn main() {
+    /// use glob::Pattern;
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::transform::TransformRule;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_transform(TransformRule::new(Coffee).matching(Pattern::new("**/*.coffee").unwrap()));
+    /// # }
+    /// ```
+    pub fn with_transform(mut self, rule: TransformRule) -> Self {
+        self.loader_mut().transforms.add(rule);
+        self
+    }
+
+    /// Send build events to a listener.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use js_bundler::deps::Deps;
+    /// use js_bundler::events::Event;
+    ///
+    /// let deps = Deps::new()
+    ///     .with_listener(|event: &Event| {
+    ///         if let Event::FileLoaded { path, .. } = *event {
+    ///             eprintln!("loaded {}", path.to_string_lossy());
+    ///         }
+    ///     });
+    /// ```
+    pub fn with_listener<L: Listener + 'static>(mut self, listener: L) -> Self {
+        self.loader_mut().listeners.add(listener);
+        self
+    }
+
+    /// Get the listeners that receive build events.
+    pub fn listeners(&self) -> &Listeners {
+        &self.loader.listeners
+    }
+
+    /// Start from modules that were loaded before, eg. by `serialize::read_graph`.
+    /// Files that are already in the module map are not loaded again by `run`.
+    pub fn with_modules(mut self, modules: ModuleMap) -> Self {
+        for record in modules.into_values() {
+            self.module_id = self.module_id.max(record.id);
+            self.loaded_files.insert(record.file.path().clone());
+            self.module_map.insert(record);
+        }
         self
     }
 
     /// Start dependency resolution at an entry file.
+    ///
+    /// Files are loaded in waves: all files that were discovered by the previous wave are read,
+    /// parsed and resolved in parallel. Module IDs are assigned in discovery order afterwards,
+    /// so the result does not depend on thread scheduling.
+    ///
+    /// If a file fails to load, none of the files from this run are added, and `run` can be
+    /// called again to retry.
     pub fn run(&mut self, entry: &str) -> Result<()> {
-        let resolved = self.resolver.with_basedir(PathBuf::from("."))
-            .resolve(entry)?;
-
-        let source_file = LoadFile::new(resolved).run()?;
-        let mut record = self.to_record(source_file, true)?;
-        let rec_path = path_to_string(&record.file.path());
-        self.loaded_files.insert(record.file.path().clone());
-        self.read_deps(&mut record)?;
-        self.add_module(&rec_path, record);
+        let resolved = self.loader.resolve_entry(entry)?;
+        let mut queue = self.start(resolved);
+        let mut unlinked = vec![];
+        while !queue.is_empty() {
+            let loaded = queue.par_iter()
+                .map(|path| self.loader.load_file(path.clone()))
+                .collect::<Vec<Result<(SourceFile, Dependencies)>>>();
+            queue = match self.add_loaded(loaded, &mut unlinked) {
+                Ok(queue) => queue,
+                Err(err) => {
+                    self.forget_unlinked();
+                    return Err(err)
+                },
+            };
+        }
+
+        self.link(unlinked);
         Ok(())
     }
 
-    fn to_record(&mut self, file: SourceFile, entry: bool) -> Result<ModuleRecord> {
-        self.module_id += 1;
-        let basedir = file.path().clone().parent().unwrap().to_path_buf();
-        let dependencies = match file {
-            SourceFile::CJS { ref dependencies, .. } => self.resolve_deps(basedir, dependencies)?,
+    /// Forget about files that were queued for loading but were never linked, so the next
+    /// `run` loads them again.
+    fn forget_unlinked(&mut self) -> () {
+        let module_map = &self.module_map;
+        self.loaded_files.retain(|path| module_map.id_of(path).is_some());
+    }
+
+    fn loader_mut(&mut self) -> &mut FileLoader {
+        Arc::get_mut(&mut self.loader)
+            .expect("Deps can't be configured while it is loading files")
+    }
+
+    /// Get the file loader, to load files on other threads.
+    pub(crate) fn loader(&self) -> Arc<FileLoader> {
+        Arc::clone(&self.loader)
+    }
+
+    /// Mark a resolved entry file as loaded, and return the first wave of files to load.
+    ///
+    /// If the file was already loaded as a dependency of another entry point, its record is
+    /// marked as an entry point instead, and there is nothing to load.
+    pub(crate) fn start(&mut self, entry: PathBuf) -> Vec<PathBuf> {
+        if let Some(id) = self.module_map.id_of(&entry) {
+            self.module_map.get_mut(id).unwrap().entry = true;
+            return vec![]
+        }
+        self.loaded_files.insert(entry.clone());
+        vec![entry]
+    }
+
+    /// Turn a wave of loaded files into records, and add them to `unlinked`.
+    /// Returns the files they depend on that have not been loaded yet, ie. the next wave.
+    pub(crate) fn add_loaded(&mut self, loaded: Vec<Result<(SourceFile, Dependencies)>>, unlinked: &mut Vec<ModuleRecord>) -> Result<Vec<PathBuf>> {
+        let mut next_queue = vec![];
+        for result in loaded {
+            let (file, dependencies) = result?;
+            for dependency in dependencies.values() {
+                if let Some(ref resolved) = dependency.resolved {
+                    if self.loaded_files.insert(resolved.clone()) {
+                        next_queue.push(resolved.clone());
+                    }
+                }
+            }
+            // The entry point is the first file we load.
+            let entry = unlinked.is_empty();
+            self.module_id += 1;
+            unlinked.push(ModuleRecord {
+                id: self.module_id,
+                file,
+                entry,
+                dependencies,
+            });
+        }
+        Ok(next_queue)
+    }
+
+    /// Link freshly loaded records to their dependencies and add them to the module map.
+    ///
+    /// Dependencies point to modules by ID, so records can be linked in any order.
+    /// Each new cycle is reported as a warning.
+    pub(crate) fn link(&mut self, unlinked: Vec<ModuleRecord>) -> () {
+        let new_ids = unlinked.iter()
+            .map(|record| record.id)
+            .collect::<Vec<u32>>();
+        for record in unlinked {
+            self.loader.listeners.emit(Event::ModuleAdded { id: record.id, path: record.file.path() });
+            self.module_map.insert(record);
+        }
+        for &id in &new_ids {
+            self.link_record(id);
+        }
+
+        let mut cycles = BTreeMap::new();
+        for (id, cycle) in cycles_of(&self.module_map) {
+            cycles.entry(cycle).or_insert_with(Vec::new).push(id);
+        }
+        for (_, mut members) in cycles {
+            if members.iter().any(|id| new_ids.contains(id)) {
+                members.sort();
+                warn_cycle(&self.cycle_path(&members), &self.loader.listeners);
+            }
+        }
+    }
+
+    /// Point a record's dependencies to the IDs of the modules they resolved to.
+    fn link_record(&mut self, id: u32) -> () {
+        let dep_ids = self.module_map.get(id).unwrap().dependencies.iter()
+            .map(|(name, dependency)| {
+                let dep_id = dependency.resolved.as_ref().and_then(|resolved| self.module_map.id_of(resolved));
+                (name.clone(), dep_id)
+            })
+            .collect::<Vec<(String, Option<u32>)>>();
+
+        for (name, dep_id) in dep_ids {
+            self.module_map.set_dependency_id(id, &name, dep_id);
+            if dep_id.is_none() {
+                let path = self.module_map.get(id).unwrap().file.path();
+                self.loader.listeners.warn(&format!("Could not resolve ModuleRecord for {} from {}", name, path.to_string_lossy()));
+            }
+        }
+    }
+
+    /// Find the paths of the modules in a cycle, in require() order, starting at the first member.
+    fn cycle_path(&self, members: &[u32]) -> Vec<PathBuf> {
+        let start = members[0];
+        let mut parents = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(id) = queue.pop_front() {
+            for dependency in self.module_map.get(id).unwrap().dependencies.values() {
+                let dep_id = match dependency.id {
+                    Some(dep_id) if members.contains(&dep_id) => dep_id,
+                    _ => continue,
+                };
+                if dep_id == start {
+                    let mut path = vec![id];
+                    while let Some(&parent) = parents.get(path.last().unwrap()) {
+                        path.push(parent);
+                    }
+                    path.reverse();
+                    return path.into_iter()
+                        .map(|id| self.module_map.get(id).unwrap().file.path().clone())
+                        .collect()
+                }
+                if dep_id != start && !parents.contains_key(&dep_id) {
+                    parents.insert(dep_id, id);
+                    queue.push_back(dep_id);
+                }
+            }
+        }
+        vec![]
+    }
+}
+
+impl FileLoader {
+    /// Resolve an entry point, relative to the current working directory.
+    pub(crate) fn resolve_entry(&self, entry: &str) -> Result<PathBuf> {
+        let resolved = self.resolver.with_basedir(PathBuf::from("."))
+            .resolve(entry)
+            .map_err(|err| Error::resolve(entry, None, err))?;
+        self.listeners.emit(Event::FileResolved { name: entry, from: None, resolved: &resolved });
+        Ok(resolved)
+    }
+
+    /// Load a file and resolve its dependencies, using the cache if possible.
+    /// This may be called from multiple threads at once.
+    pub(crate) fn load_file(&self, path: PathBuf) -> Result<(SourceFile, Dependencies)> {
+        let package = self.packages.for_file(&path, &self.resolver)?;
+        let mut transforms = package.transforms;
+        transforms.extend(self.transforms.clone());
+        let cache_key = transforms.cache_key(&path);
+        let is_module = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("mjs") => true,
+            Some("cjs") => false,
+            _ => package.is_module,
+        };
+        let options = CacheOptions {
+            include_builtins: self.include_builtins,
+            // `process` and `Buffer` can only be inserted if there are shims to resolve them to.
+            insert_builtin_globals: self.include_builtins && self.builtins.is_builtin("process"),
+            is_module,
+        };
+        let loader = LoadFile::new(path.clone())
+            .with_transforms(transforms)
+            .as_module(is_module)
+            .include_builtins(options.insert_builtin_globals)
+            .with_listeners(self.listeners.clone());
+        let (source, hash) = loader.read_source()?;
+
+        let cached = self.cache.as_ref()
+            .and_then(|cache| cache.get(&path, &hash, options, &cache_key));
+        let file = match cached {
+            Some(cached) => {
+                self.listeners.emit(Event::FileLoaded { path: &path, cached: true });
+                cached.into_source_file(path, hash)
+            },
+            None => {
+                let file = loader.run_source(source, hash)?;
+                self.listeners.emit(Event::FileLoaded { path: &path, cached: false });
+                if let Some(ref cache) = self.cache {
+                    if let Some(cache_entry) = CacheEntry::new(&file, options, cache_key) {
+                        cache.put(&path, &cache_entry)?;
+                    }
+                }
+                file
+            },
+        };
+        // Dependencies are resolved again even for cached files, because new files or packages
+        // may change what they resolve to.
+        let dependencies = self.file_deps(&file)?;
+        Ok((file, dependencies))
+    }
+
+    fn file_deps(&self, file: &SourceFile) -> Result<Dependencies> {
+        let path = file.path();
+        let dependencies = match *file {
+            SourceFile::CJS { ref dependencies, ref lazy_dependencies, ref contexts, .. } => {
+                let mut map = self.resolve_deps(path, lazy_dependencies, &vec![])?
+                    .into_iter()
+                    .map(|(dep_id, dependency)| (dep_id, dependency.lazy()))
+                    .collect::<Dependencies>();
+                // If a module is also required synchronously, it can't be split out.
+                map.extend(self.resolve_deps(path, dependencies, contexts)?);
+                map
+            },
             _ => Dependencies::new(),
         };
-        Ok(ModuleRecord {
-            id: self.module_id,
-            file,
-            entry,
-            dependencies,
-        })
+        Ok(dependencies)
     }
 
-    fn resolve_deps(&mut self, basedir: PathBuf, dependencies: &Vec<String>) -> Result<Dependencies> {
-        let resolver = self.resolver.with_basedir(basedir);
+    fn resolve_deps(&self, from: &Path, dependencies: &Vec<String>, contexts: &Vec<RequireContext>) -> Result<Dependencies> {
+        let basedir = from.parent().unwrap().to_path_buf();
+        let resolver = self.resolver.with_basedir(basedir.clone());
         let mut map = Dependencies::new();
         for dep_id in dependencies {
             // TODO include core module shims
             let path = if self.builtins.is_builtin(&dep_id) {
                 if self.include_builtins {
-                    self.builtins.resolve(&resolver, &dep_id)?
+                    self.builtins.resolve(&resolver, &dep_id, from)?
                 } else {
                     None
                 }
             } else {
-                Some(resolver.resolve(&dep_id)?)
+                Some(resolver.resolve(&dep_id).map_err(|err| Error::resolve(&dep_id, Some(from), err))?)
             };
-            path.map(|resolved| map.insert(dep_id.clone(), Dependency::resolved(dep_id.clone(), resolved)));
+            if let Some(resolved) = path {
+                self.listeners.emit(Event::FileResolved { name: dep_id, from: Some(from), resolved: &resolved });
+                map.insert(dep_id.clone(), Dependency::resolved(dep_id.clone(), resolved));
+            }
         }
-        Ok(map)
-    }
-
-    fn read_deps(&mut self, record: &mut ModuleRecord) -> Result<()> {
-        for dependency in record.dependencies.values_mut() {
-            let dep_record = if let Some(ref resolved) = dependency.resolved {
-                if !self.loaded_files.contains(resolved) {
-                    let source_file = LoadFile::new(resolved.clone()).run()?;
-                    let mut new_record = self.to_record(source_file, true)?;
-                    let new_path = path_to_string(&new_record.file.path());
-                    self.loaded_files.insert(new_record.file.path().to_path_buf());
-                    self.read_deps(&mut new_record)?;
-                    self.add_module(&new_path, new_record);
-                }
-                self.module_map.get(&path_to_string(resolved)).map(|rc| rc.to_owned())
-            } else {
-                None
-            };
-
-            if dep_record.is_none() {
-                warn!("Could not resolve ModuleRecord for {} from {}", dependency.name, record.file.path().to_string_lossy());
+        // Include every file that a computed require() may point to. The runtime looks up the
+        // computed ID in the dependency map, so it resolves just like a static require().
+        for context in contexts {
+            for (dep_id, resolved) in expand_context(from, context, EXTENSIONS, &self.listeners)? {
+                self.listeners.emit(Event::FileResolved { name: &dep_id, from: Some(from), resolved: &resolved });
+                map.insert(dep_id.clone(), Dependency::resolved(dep_id, resolved));
             }
-            dep_record.map(|d| dependency.set_record(&d));
         }
-        Ok(())
-    }
-
-    fn add_module(&mut self, rec_path: &str, record: ModuleRecord) -> () {
-        self.module_map.insert(rec_path.to_string(), Rc::new(record));
+        Ok(map)
     }
 }
 
@@ -169,6 +474,114 @@ impl Deref for Deps {
     }
 }
 
-fn path_to_string(path: &Path) -> String {
-    path.to_string_lossy().into_owned()
+fn warn_cycle(cycle: &[PathBuf], listeners: &Listeners) -> () {
+    let names = cycle.iter()
+        .chain(cycle.first())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    listeners.warn(&format!("Circular dependency: {}", names.join(" -> ")));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{File, create_dir_all, remove_dir_all};
+    use std::io::Write;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use cache::Cache;
+    use events::Event;
+    use graph::cycles;
+    use super::Deps;
+
+    fn write_file(path: &Path, source: &str) -> () {
+        File::create(path).unwrap()
+            .write_all(source.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn cached_files_find_new_context_matches() {
+        let dir = env::temp_dir().join("js-bundler-test-cached-context");
+        let _ = remove_dir_all(&dir);
+        create_dir_all(dir.join("pages")).unwrap();
+        write_file(&dir.join("index.js"), "var name = 'a'; require('./pages/' + name + '.js');");
+        write_file(&dir.join("pages/a.js"), "");
+
+        let names = || {
+            let mut deps = Deps::new().with_cache(Cache::new(dir.join("cache")).unwrap());
+            deps.run(dir.join("index.js").to_str().unwrap()).unwrap();
+            let index = deps.values().find(|record| record.file.path().ends_with("index.js")).unwrap();
+            index.dependencies.keys().cloned().collect::<Vec<String>>()
+        };
+        assert_eq!(names(), vec!["./pages/a.js"]);
+        write_file(&dir.join("pages/b.js"), "");
+        assert_eq!(names(), vec!["./pages/a.js", "./pages/b.js"]);
+    }
+    #[test]
+    fn cached_files_resolve_dependencies_again() {
+        let dir = env::temp_dir().join("js-bundler-test-cached-resolve");
+        let _ = remove_dir_all(&dir);
+        create_dir_all(dir.join("lib")).unwrap();
+        write_file(&dir.join("index.js"), "require('./lib');");
+        write_file(&dir.join("lib/index.js"), "");
+
+        let resolved = || {
+            let mut deps = Deps::new().with_cache(Cache::new(dir.join("cache")).unwrap());
+            deps.run(dir.join("index.js").to_str().unwrap()).unwrap();
+            let index = deps.values().find(|record| record.entry).unwrap();
+            index.dependencies["./lib"].resolved.clone().unwrap()
+        };
+        assert_eq!(resolved(), dir.join("lib/index.js"));
+        // A file next to the directory takes precedence.
+        write_file(&dir.join("lib.js"), "");
+        assert_eq!(resolved(), dir.join("lib.js"));
+    }
+
+    #[test]
+    fn can_run_again_after_an_error() {
+        let dir = env::temp_dir().join("js-bundler-test-retry");
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        write_file(&dir.join("index.js"), "require('./a'); require('./broken');");
+        write_file(&dir.join("a.js"), "require('./b');");
+        write_file(&dir.join("b.js"), "");
+        write_file(&dir.join("broken.js"), "require(");
+
+        let warnings = Arc::new(Mutex::new(vec![]));
+        let listener_warnings = Arc::clone(&warnings);
+        let mut deps = Deps::new().with_listener(move |event: &Event| {
+            if let Event::Warning(message) = *event {
+                listener_warnings.lock().unwrap().push(message.to_string());
+            }
+        });
+        let entry = dir.join("index.js");
+        assert!(deps.run(entry.to_str().unwrap()).is_err());
+        assert_eq!(deps.len(), 0);
+
+        write_file(&dir.join("broken.js"), "require('./b');");
+        deps.run(entry.to_str().unwrap()).unwrap();
+        assert_eq!(deps.len(), 4);
+        assert!(warnings.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn links_deep_chains_without_recursing() {
+        // Deep enough to overflow the stack of a test thread if linking or finding cycles
+        // recursed once per module.
+        const LENGTH: usize = 5000;
+        let dir = env::temp_dir().join("js-bundler-test-deep-chain");
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        for i in 0..LENGTH {
+            // The last module requires the first one again, so the whole chain is one cycle.
+            write_file(&dir.join(format!("m{}.js", i)), &format!("require('./m{}');", (i + 1) % LENGTH));
+        }
+
+        let mut deps = Deps::new();
+        deps.run(dir.join("m0.js").to_str().unwrap()).unwrap();
+        assert_eq!(deps.len(), LENGTH);
+        let cycles = cycles(&deps);
+        assert_eq!(cycles.len(), LENGTH);
+        assert!(cycles.values().all(|&cycle| cycle == cycles[&1]));
+    }
 }
@@ -0,0 +1,173 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde_json;
+use loader::ParseError;
+
+/// Everything that can go wrong while building a bundle.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io {
+        /// The file that could not be read or written, if known.
+        path: Option<PathBuf>,
+        cause: io::Error,
+    },
+    /// A javascript file could not be parsed.
+    Parse(ParseError),
+    /// A JSON file could not be parsed, or a value could not be serialized.
+    Json {
+        /// The file that could not be parsed or written, if known.
+        path: Option<PathBuf>,
+        cause: serde_json::Error,
+    },
+    /// A module ID could not be resolved to a file.
+    Resolve {
+        /// The module ID that was passed to require().
+        name: String,
+        /// The file containing the require() call, or `None` for entry points.
+        from: Option<PathBuf>,
+        cause: Box<StdError + Send + Sync>,
+    },
+    /// A transform failed on a file.
+    Transform {
+        path: PathBuf,
+        /// Name of the transform.
+        transform: String,
+        message: String,
+    },
+    /// A Node builtin module has no known shim.
+    MissingBuiltin(String),
+    /// The bundler was used incorrectly, or was given a malformed module graph.
+    InvalidInput(String),
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// Create an error for a failed file operation.
+    pub fn io(path: &Path, cause: io::Error) -> Error {
+        Error::Io { path: Some(path.to_path_buf()), cause }
+    }
+
+    /// Create an error for invalid JSON in a file.
+    pub fn json(path: &Path, cause: serde_json::Error) -> Error {
+        Error::Json { path: Some(path.to_path_buf()), cause }
+    }
+
+    /// Create an error for a module ID that could not be resolved.
+    pub fn resolve<E>(name: &str, from: Option<&Path>, cause: E) -> Error
+        where E: StdError + Send + Sync + 'static
+    {
+        Error::Resolve {
+            name: name.to_string(),
+            from: from.map(Path::to_path_buf),
+            cause: Box::new(cause),
+        }
+    }
+
+    /// Create an error for a transform that failed on a file.
+    pub fn transform(path: &Path, transform: &str, message: String) -> Error {
+        Error::Transform {
+            path: path.to_path_buf(),
+            transform: transform.to_string(),
+            message,
+        }
+    }
+
+    /// Get the file that caused the error, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            Error::Io { ref path, .. } | Error::Json { ref path, .. } => path.as_ref().map(|path| path.as_path()),
+            Error::Parse(ref error) => Some(error.filename()),
+            Error::Resolve { ref from, .. } => from.as_ref().map(|path| path.as_path()),
+            Error::Transform { ref path, .. } => Some(path),
+            Error::MissingBuiltin(_) | Error::InvalidInput(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io { path: Some(ref path), ref cause } =>
+                write!(f, "{}: {}", path.to_string_lossy(), cause),
+            Error::Io { path: None, ref cause } => write!(f, "{}", cause),
+            Error::Parse(ref error) => write!(f, "{}", error),
+            Error::Json { path: Some(ref path), ref cause } =>
+                write!(f, "Invalid JSON in {}: {}", path.to_string_lossy(), cause),
+            Error::Json { path: None, ref cause } => write!(f, "Invalid JSON: {}", cause),
+            Error::Resolve { ref name, from: Some(ref from), ref cause } =>
+                write!(f, "Could not resolve '{}' from {}: {}", name, from.to_string_lossy(), cause),
+            Error::Resolve { ref name, from: None, ref cause } =>
+                write!(f, "Could not resolve '{}': {}", name, cause),
+            Error::Transform { ref path, ref transform, ref message } =>
+                write!(f, "Transform {} failed on {}: {}", transform, path.to_string_lossy(), message),
+            Error::MissingBuiltin(ref name) => write!(f, "Missing builtin mapping for {}", name),
+            Error::InvalidInput(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io { .. } => "io error",
+            Error::Parse(_) => "parse error",
+            Error::Json { .. } => "json error",
+            Error::Resolve { .. } => "resolve error",
+            Error::Transform { .. } => "transform error",
+            Error::MissingBuiltin(_) => "missing builtin",
+            Error::InvalidInput(_) => "invalid input",
+        }
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            Error::Io { ref cause, .. } => Some(cause),
+            Error::Parse(ref error) => Some(error),
+            Error::Json { ref cause, .. } => Some(cause),
+            Error::Resolve { ref cause, .. } => Some(&**cause),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(cause: io::Error) -> Error {
+        Error::Io { path: None, cause }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(cause: serde_json::Error) -> Error {
+        Error::Json { path: None, cause }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use deps::Deps;
+    use super::Error;
+
+    #[test]
+    fn resolve_errors_name_the_requiring_file() {
+        let mut deps = Deps::new();
+        match deps.run("./tests/fixtures/missing/index.js") {
+            Err(Error::Resolve { ref name, ref from, .. }) => {
+                assert_eq!(name, "./does-not-exist");
+                assert!(from.as_ref().unwrap().ends_with(Path::new("tests/fixtures/missing/index.js")));
+            },
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(()) => panic!("expected an error"),
+        }
+    }
+}
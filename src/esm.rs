@@ -0,0 +1,314 @@
+//! Convert ES modules to CommonJS.
+//!
+//! `import` declarations become `require()` calls at the top of the module, so the usual
+//! require() detection finds them as dependencies. Exports are defined as getters on `exports`,
+//! so they stay up to date when the exported variables change, and `exports.__esModule` is set
+//! so that other ES modules can tell the default export apart from `module.exports`.
+//!
+//! Export getters are defined before any other module is required, so in a circular import,
+//! the other module can already use exported function declarations, which are hoisted.
+//!
+//! Imported bindings are copied into local variables when the module runs, so unlike real ES
+//! modules, they do not see later changes in the exporting module. This is a known gap: in a
+//! circular import, a module that runs first sees `undefined` for the exported variables of a
+//! module that has not finished running yet, and keeps seeing it after that module finishes.
+
+use easter::decl::{ConstDtor, Decl, Dtor, Export, ExportSpec, Import, ImportSpec};
+use easter::patt::Patt;
+use easter::stmt::{ModItem, Module};
+use joker::track::TrackingRef;
+
+/// Runtime helpers for default and namespace imports of CommonJS modules.
+const IMPORT_DEFAULT: &str = "function __importDefault(m) { return m && m.__esModule ? m['default'] : m; }";
+const IMPORT_STAR: &str = "function __importStar(m) { if (m && m.__esModule) return m; var ns = { 'default': m }; \
+if (m != null) for (var k in m) if (Object.prototype.hasOwnProperty.call(m, k)) ns[k] = m[k]; return ns; }";
+const EXPORT_STAR: &str = "function __exportStar(m) { Object.keys(m).forEach(function (k) { \
+if (k !== 'default' && !Object.prototype.hasOwnProperty.call(exports, k)) \
+Object.defineProperty(exports, k, { enumerable: true, get: function () { return m[k]; } }); }); }";
+
+/// Rewrite the source code of an ES module to CommonJS.
+pub fn to_commonjs(source: &str, module: &Module) -> String {
+    // Source locations count characters, not bytes.
+    let byte_offsets = source.char_indices()
+        .map(|(index, _)| index)
+        .chain(Some(source.len()))
+        .collect::<Vec<usize>>();
+    let to_byte = |offset: u32| byte_offsets[(offset as usize).min(byte_offsets.len() - 1)];
+
+    let mut converter = Converter::default();
+    let mut body = String::new();
+    let mut last = 0;
+    for item in &module.items {
+        let keyword = match *item {
+            ModItem::Import(_) => "import",
+            ModItem::Export(_) => "export",
+            ModItem::StmtListItem(_) => continue,
+        };
+        let span = match *item.tracking_ref() {
+            Some(span) => span,
+            None => continue,
+        };
+        // Declarations like `export function` may not include the `export` keyword in their
+        // location, so look for it before the start of the item.
+        let item_start = to_byte(span.start.offset);
+        let mut search_end = (item_start + keyword.len()).min(source.len());
+        while !source.is_char_boundary(search_end) {
+            search_end += 1;
+        }
+        let start = source[last..search_end].rfind(keyword).map_or(item_start, |index| last + index);
+        let end = to_byte(span.end.offset);
+        body.push_str(&source[last..start]);
+        match *item {
+            ModItem::Import(ref import) => converter.import(import),
+            ModItem::Export(ref export) => body.push_str(&converter.export(export, &source[start + keyword.len()..end])),
+            ModItem::StmtListItem(_) => (),
+        }
+        last = end;
+    }
+    body.push_str(&source[last..]);
+
+    let mut output = String::from("'use strict';\n");
+    output.push_str("Object.defineProperty(exports, '__esModule', { value: true });\n");
+    for line in converter.exports.into_iter().chain(converter.preamble) {
+        output.push_str(&line);
+        output.push('\n');
+    }
+    output.push_str(&body);
+    output
+}
+
+#[derive(Default)]
+struct Converter {
+    /// Export getters, which are defined before anything else runs.
+    exports: Vec<String>,
+    /// Statements to run before the module body, after the export getters: helpers and imports.
+    preamble: Vec<String>,
+    /// Number of modules that were required so far, for generating variable names.
+    num_imports: usize,
+    uses_import_default: bool,
+    uses_import_star: bool,
+    uses_export_star: bool,
+}
+
+impl Converter {
+    /// Add a require() call to the preamble, and return the name of the variable it is stored in.
+    fn require(&mut self, source: &str) -> String {
+        let name = format!("__module{}", self.num_imports);
+        self.num_imports += 1;
+        self.preamble.push(format!("var {} = require({});", name, quote(source)));
+        name
+    }
+
+    /// Hoist an import declaration into the preamble. The declaration itself is removed.
+    fn import(&mut self, import: &Import) -> () {
+        match *import {
+            Import::ForEffect(_, ref source) => {
+                self.preamble.push(format!("require({});", quote(&source.value)));
+            },
+            Import::Named(_, ref default, ref specs, ref source) => {
+                let module = self.require(&source.value);
+                if let Some(ref default) = *default {
+                    self.import_default(default.name.as_ref(), &module);
+                }
+                for spec in specs {
+                    self.import_named(spec, &module);
+                }
+            },
+            Import::Namespace(_, ref default, ref namespace, ref source) => {
+                let module = self.require(&source.value);
+                if let Some(ref default) = *default {
+                    self.import_default(default.name.as_ref(), &module);
+                }
+                if !self.uses_import_star {
+                    self.uses_import_star = true;
+                    self.preamble.push(IMPORT_STAR.to_string());
+                }
+                self.preamble.push(format!("var {} = __importStar({});", namespace.name.as_ref(), module));
+            },
+        }
+    }
+
+    fn import_default(&mut self, local: &str, module: &str) -> () {
+        if !self.uses_import_default {
+            self.uses_import_default = true;
+            self.preamble.push(IMPORT_DEFAULT.to_string());
+        }
+        self.preamble.push(format!("var {} = __importDefault({});", local, module));
+    }
+
+    fn import_named(&mut self, spec: &ImportSpec, module: &str) -> () {
+        let imported = spec.imported.name.as_ref();
+        if imported == "default" {
+            self.import_default(spec.local.name.as_ref(), module);
+        } else {
+            self.preamble.push(format!("var {} = {}[{}];", spec.local.name.as_ref(), module, quote(imported)));
+        }
+    }
+
+    /// Define a getter for an export.
+    fn export_binding(&mut self, exported: &str, value: &str) -> () {
+        self.exports.push(format!(
+            "Object.defineProperty(exports, {}, {{ enumerable: true, get: function () {{ return {}; }} }});",
+            quote(exported), value));
+    }
+
+    /// Convert an export declaration. `rest` is the source code after the `export` keyword.
+    fn export(&mut self, export: &Export, rest: &str) -> String {
+        match *export {
+            // export var a = 1, b = 2
+            Export::Var(_, ref dtors) => {
+                for name in dtors.iter().filter_map(dtor_name) {
+                    self.export_binding(name, name);
+                }
+                rest.trim_left().to_string()
+            },
+            // export function f() {}, export let a = 1, export const b = 2
+            Export::Decl(ref decl) => {
+                let names = match *decl {
+                    Decl::Fun(ref fun) => vec![fun.id.name.as_ref()],
+                    Decl::Let(_, ref dtors, _) => dtors.iter().filter_map(dtor_name).collect(),
+                    Decl::Const(_, ref dtors, _) => dtors.iter().filter_map(const_dtor_name).collect(),
+                };
+                for name in names {
+                    self.export_binding(name, name);
+                }
+                rest.trim_left().to_string()
+            },
+            // export default function f() {}
+            Export::DefaultFun(..) => {
+                let declaration = strip_default(rest);
+                match function_name(declaration) {
+                    Some(name) => {
+                        self.export_binding("default", name);
+                        declaration.to_string()
+                    },
+                    None => format!("exports['default'] = {}", declaration),
+                }
+            },
+            // export default 1 + 2
+            Export::DefaultExpr(..) => format!("exports['default'] = {}", strip_default(rest)),
+            // export { a, b as c }
+            Export::Local(_, ref specs) => {
+                for spec in specs {
+                    self.export_spec(spec, None);
+                }
+                String::new()
+            },
+            // export { a, b as c } from 'x'
+            Export::Reexport(_, ref specs, ref source) => {
+                let module = self.require(&source.value);
+                for spec in specs {
+                    self.export_spec(spec, Some(&module));
+                }
+                String::new()
+            },
+            // export * from 'x'
+            Export::Glob(_, ref source) => {
+                let module = self.require(&source.value);
+                if !self.uses_export_star {
+                    self.uses_export_star = true;
+                    self.preamble.push(EXPORT_STAR.to_string());
+                }
+                self.preamble.push(format!("__exportStar({});", module));
+                String::new()
+            },
+        }
+    }
+
+    fn export_spec(&mut self, spec: &ExportSpec, module: Option<&str>) -> () {
+        let local = spec.local.name.as_ref();
+        let value = match module {
+            Some(module) => format!("{}[{}]", module, quote(local)),
+            None => local.to_string(),
+        };
+        self.export_binding(spec.exported.name.as_ref(), &value);
+    }
+}
+
+/// Get the name of a simple `var`/`let` declarator. Destructuring exports are not supported.
+fn dtor_name(dtor: &Dtor) -> Option<&str> {
+    match *dtor {
+        Dtor::Simple(_, ref id, _) => Some(id.name.as_ref()),
+        _ => None,
+    }
+}
+
+/// Get the name of a simple `const` declarator.
+fn const_dtor_name(dtor: &ConstDtor) -> Option<&str> {
+    match dtor.patt {
+        Patt::Simple(ref id) => Some(id.name.as_ref()),
+        _ => None,
+    }
+}
+
+fn strip_default(rest: &str) -> &str {
+    let rest = rest.trim_left();
+    if rest.starts_with("default") {
+        rest["default".len()..].trim_left()
+    } else {
+        rest
+    }
+}
+
+/// Get the name of a function declaration from its source, like `f` in `function* f() {}`.
+fn function_name(declaration: &str) -> Option<&str> {
+    let rest = declaration.trim_left();
+    if !rest.starts_with("function") {
+        return None
+    }
+    let rest = rest["function".len()..].trim_left().trim_left_matches('*').trim_left();
+    let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len());
+    if end == 0 { None } else { Some(&rest[..end]) }
+}
+
+/// Quote a string as a javascript string literal.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use esprit::{module, script};
+    use super::to_commonjs;
+
+    fn convert(source: &str) -> String {
+        let output = to_commonjs(source, &module(source).unwrap());
+        // The output must be a valid script.
+        script(&output).unwrap();
+        output
+    }
+
+    #[test]
+    fn converts_imports_to_requires() {
+        let output = convert("import a, { b as c } from './a'\nimport * as d from 'd'\nimport './e'\nc(a, d)\n");
+        assert!(output.contains("var __module0 = require('./a');"));
+        assert!(output.contains("var a = __importDefault(__module0);"));
+        assert!(output.contains("var c = __module0['b'];"));
+        assert!(output.contains("var d = __importStar(__module1);"));
+        assert!(output.contains("require('./e');"));
+        assert!(output.ends_with("\nc(a, d)\n"));
+    }
+
+    #[test]
+    fn converts_exports_to_getters() {
+        let output = convert("export var a = 1\nexport function b() {}\nexport { a as c }\nexport default a + 1\n");
+        assert!(output.contains("Object.defineProperty(exports, '__esModule', { value: true });"));
+        assert!(output.contains("Object.defineProperty(exports, 'a', { enumerable: true, get: function () { return a; } });"));
+        assert!(output.contains("Object.defineProperty(exports, 'c', { enumerable: true, get: function () { return a; } });"));
+        assert!(output.contains("\nvar a = 1\nfunction b() {}\n"));
+        assert!(output.contains("exports['default'] = a + 1"));
+    }
+    #[test]
+    fn defines_exports_before_imports() {
+        let output = convert("import { b } from './b'\nexport function a() {}\n");
+        let getter = output.find("Object.defineProperty(exports, 'a'").unwrap();
+        assert!(getter < output.find("require('./b')").unwrap());
+    }
+    #[test]
+    fn handles_non_ascii_source() {
+        let output = convert("var greeting = 'héllo wörld ✓'\nexport { greeting }\nexport default 'ü'\n");
+        assert!(output.contains("\nvar greeting = 'héllo wörld ✓'\n"));
+        assert!(output.contains("exports['default'] = 'ü'"));
+        assert!(!output.contains("export {"));
+    }
+}
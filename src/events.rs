@@ -0,0 +1,115 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Something that happened during a build.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A module ID was resolved to a file. `from` is `None` for entry points.
+    FileResolved {
+        name: &'a str,
+        from: Option<&'a Path>,
+        resolved: &'a Path,
+    },
+    /// A file was loaded, either by parsing it or from the cache.
+    FileLoaded {
+        path: &'a Path,
+        cached: bool,
+    },
+    /// A transform was applied to a file.
+    TransformApplied {
+        path: &'a Path,
+        transform: &'a str,
+    },
+    /// A module was added to the module graph.
+    ModuleAdded {
+        id: u32,
+        path: &'a Path,
+    },
+    /// Something looks wrong, but the build can continue.
+    Warning(&'a str),
+    /// `Bundler` generated a bundle file in memory. It is up to the caller to write it.
+    /// `filename` is `None` for the main bundle, whose file name is chosen by the caller.
+    BundleGenerated {
+        filename: Option<&'a str>,
+        size: usize,
+    },
+}
+
+/// Receives build events.
+///
+/// Files are loaded on multiple threads, so listeners may be called from any thread,
+/// and from multiple threads at once.
+pub trait Listener: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl<F> Listener for F where F: Fn(&Event) + Send + Sync {
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// A list of listeners.
+#[derive(Clone, Default)]
+pub struct Listeners {
+    listeners: Vec<Arc<Listener>>,
+}
+
+impl Listeners {
+    pub fn new() -> Listeners {
+        Listeners::default()
+    }
+
+    pub fn add<L: Listener + 'static>(&mut self, listener: L) -> () {
+        self.listeners.push(Arc::new(listener));
+    }
+
+    pub fn emit(&self, event: Event) -> () {
+        for listener in &self.listeners {
+            listener.on_event(&event);
+        }
+    }
+
+    /// Log a warning, and emit it as an event.
+    pub fn warn(&self, message: &str) -> () {
+        warn!("{}", message);
+        self.emit(Event::Warning(message));
+    }
+}
+
+impl fmt::Debug for Listeners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Listeners({})", self.listeners.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use deps::Deps;
+    use super::Event;
+
+    #[test]
+    fn reports_loaded_files_and_transforms() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let events = Arc::clone(&log);
+        let mut deps = Deps::new()
+            .with_listener(move |event: &Event| {
+                let line = match *event {
+                    Event::FileLoaded { path, .. } => format!("loaded {}", path.file_name().unwrap().to_string_lossy()),
+                    Event::TransformApplied { path, transform } => format!("{} {}", transform, path.file_name().unwrap().to_string_lossy()),
+                    Event::ModuleAdded { id, .. } => format!("added {}", id),
+                    _ => return,
+                };
+                events.lock().unwrap().push(line);
+            });
+        deps.run("./tests/fixtures/module-exports/index.js").unwrap();
+
+        let log = log.lock().unwrap();
+        assert!(log.contains(&"loaded index.js".to_string()));
+        assert!(log.contains(&"json data.json".to_string()));
+        assert!(!log.contains(&"json index.js".to_string()));
+        assert_eq!(log.iter().filter(|line| line.starts_with("added ")).count(), deps.len());
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+use graph::{ModuleMap, ModuleRecord};
+
+/// Modules grouped by the entry points that use them.
+pub struct Factored {
+    /// IDs of modules that are used by more than one entry point.
+    pub common: HashSet<u32>,
+    /// Pairs of entry point IDs and the IDs of modules that only that entry point uses.
+    /// Sorted by entry point ID.
+    pub entries: Vec<(u32, HashSet<u32>)>,
+}
+
+/// Split modules into a common group, for modules that are used by more than one
+/// entry point, and a group for each entry point with the modules only it uses.
+///
+/// Modules that are not reachable from any entry point end up in the common group.
+pub fn factor(modules: &ModuleMap) -> Factored {
+    let mut entry_records = modules.values()
+        .filter(|record| record.entry)
+        .collect::<Vec<&ModuleRecord>>();
+    entry_records.sort_by_key(|record| record.id);
+
+    let reachable = entry_records.iter()
+        .map(|record| (record.id, reachable_from(record.id, modules)))
+        .collect::<Vec<(u32, HashSet<u32>)>>();
+
+    let mut users = HashMap::new();
+    for &(_, ref ids) in &reachable {
+        for &id in ids {
+            *users.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    let common = modules.values()
+        .map(|record| record.id)
+        .filter(|id| users.get(id).map_or(true, |&count| count > 1))
+        .collect::<HashSet<u32>>();
+    let entries = reachable.into_iter()
+        .map(|(entry, ids)| {
+            let exclusive = ids.into_iter()
+                .filter(|id| !common.contains(id))
+                .collect();
+            (entry, exclusive)
+        })
+        .collect();
+
+    Factored { common, entries }
+}
+
+/// Find the IDs of all modules that `id` depends on, directly or indirectly, including itself.
+fn reachable_from(id: u32, modules: &ModuleMap) -> HashSet<u32> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        if !seen.insert(id) {
+            continue
+        }
+        if let Some(record) = modules.get(id) {
+            stack.extend(record.dependencies.values().filter_map(|dependency| dependency.id));
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use deps::Deps;
+    use super::factor;
+
+    #[test]
+    fn entries_required_by_other_entries_are_common() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/overlap/a.js").unwrap();
+        deps.run("./tests/fixtures/overlap/b.js").unwrap();
+        assert_eq!(deps.len(), 2);
+
+        let a = deps.values().find(|record| record.file.path().ends_with("a.js")).unwrap().id;
+        let b = deps.values().find(|record| record.file.path().ends_with("b.js")).unwrap().id;
+        assert!(deps.get(b).unwrap().entry);
+
+        let factored = factor(&deps);
+        assert_eq!(factored.common, vec![b].into_iter().collect::<HashSet<u32>>());
+        assert_eq!(factored.entries, vec![
+            (a, vec![a].into_iter().collect::<HashSet<u32>>()),
+            (b, HashSet::new()),
+        ]);
+    }
+}
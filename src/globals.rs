@@ -0,0 +1,102 @@
+use std::env;
+use std::path::Path;
+use serde_json;
+
+/// Node globals that we can provide in the browser, and the values to pass for them.
+/// `process` and `Buffer` come from the node-core-shims packages, so they are only available
+/// when builtins are included. The others need no shim.
+const GLOBALS: &[(&str, bool)] = &[
+    ("process", true),
+    ("global", false),
+    ("Buffer", true),
+    ("__filename", false),
+    ("__dirname", false),
+];
+
+/// Wrap a module in a function that provides the Node globals it uses, like browserify's
+/// insert-module-globals. `free_variables` are the variables that the module uses without
+/// declaring them.
+///
+/// Returns the wrapped source and the modules that the wrapper requires, or `None` if the
+/// module does not use any of the globals.
+pub fn insert_globals(path: &Path, source: &str, free_variables: &[String], include_builtins: bool) -> Option<(String, Vec<String>)> {
+    let used = GLOBALS.iter()
+        .filter(|&&(_, needs_builtins)| include_builtins || !needs_builtins)
+        .filter(|&&(name, _)| free_variables.iter().any(|free| free == name))
+        .map(|&(name, _)| name)
+        .collect::<Vec<&str>>();
+    if used.is_empty() {
+        return None
+    }
+
+    let mut dependencies = vec![];
+    let values = used.iter()
+        .map(|&name| match name {
+            "process" => {
+                dependencies.push("process".to_string());
+                "require('process')".to_string()
+            },
+            "global" => "typeof global !== 'undefined' ? global : typeof self !== 'undefined' ? self : typeof window !== 'undefined' ? window : {}".to_string(),
+            "Buffer" => {
+                dependencies.push("buffer".to_string());
+                "require('buffer').Buffer".to_string()
+            },
+            "__filename" => quote(&filename(path)),
+            "__dirname" => quote(&dirname(&filename(path))),
+            _ => unreachable!(),
+        })
+        .collect::<Vec<String>>();
+    // Keep the module source on the first line, so line numbers in errors still match.
+    let wrapped = format!("(function ({}) {{{}\n}}).call(this, {})", used.join(", "), source, values.join(", "));
+    Some((wrapped, dependencies))
+}
+
+/// Get the path of a file relative to the current directory, like `/src/app.js`, so bundles
+/// do not contain the full path to the project.
+fn filename(path: &Path) -> String {
+    let relative = env::current_dir().ok()
+        .and_then(|cwd| path.strip_prefix(&cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf());
+    let name = relative.to_string_lossy().replace('\\', "/");
+    let name = name.trim_left_matches("./");
+    if name.starts_with('/') { name.to_string() } else { format!("/{}", name) }
+}
+
+fn dirname(filename: &str) -> String {
+    match filename.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => filename[..index].to_string(),
+    }
+}
+
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::insert_globals;
+
+    #[test]
+    fn inserts_used_globals() {
+        let free = vec!["process".to_string(), "__dirname".to_string(), "require".to_string()];
+        let (wrapped, required) = insert_globals(Path::new("./src/app.js"), "f(process, __dirname)", &free, true).unwrap();
+        assert_eq!(wrapped, "(function (process, __dirname) {f(process, __dirname)\n}).call(this, require('process'), \"/src\")");
+        assert_eq!(required, vec!["process"]);
+
+        let (wrapped, required) = insert_globals(Path::new("./src/app.js"), "f(process, __dirname)", &free, false).unwrap();
+        assert_eq!(wrapped, "(function (__dirname) {f(process, __dirname)\n}).call(this, \"/src\")");
+        assert!(required.is_empty());
+
+        assert!(insert_globals(Path::new("./src/app.js"), "f()", &["f".to_string()], true).is_none());
+    }
+
+    #[test]
+    fn inserts_global_without_builtins() {
+        let free = vec!["global".to_string(), "Buffer".to_string()];
+        let (wrapped, required) = insert_globals(Path::new("./src/app.js"), "f(global)", &free, false).unwrap();
+        assert!(wrapped.starts_with("(function (global) {f(global)\n}).call(this, typeof global !== 'undefined' ? global"));
+        assert!(required.is_empty());
+    }
+}
@@ -1,18 +1,34 @@
-use std::cmp::Ordering;
-use std::collections::{HashMap, BTreeMap};
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::cmp::{min, Ordering};
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use digest::generic_array::GenericArray;
 use digest::generic_array::typenum::U20;
 use easter::stmt::Script;
+use estree_detect_requires::RequireContext;
 use serde_json::Value;
 
 /// Map dependency IDs used inside require() to their full paths.
 pub type Dependencies = BTreeMap<String, Dependency>;
 pub type Hash = GenericArray<u8, U20>;
 
+/// Hex encode a hash.
+pub fn hash_to_hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode a hex encoded hash. Returns `None` if `hex` is not a valid hash.
+pub fn hash_from_hex(hex: &str) -> Option<Hash> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None
+    }
+    let bytes = (0..20)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(GenericArray::clone_from_slice(&bytes))
+}
+
 /// A source file.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SourceFile {
     CJS {
         /// Path to the file.
@@ -25,6 +41,10 @@ pub enum SourceFile {
         ast: Option<Script>,
         /// Dependencies.
         dependencies: Vec<String>,
+        /// Dependencies that are loaded on demand using `require.ensure()`.
+        lazy_dependencies: Vec<String>,
+        /// Dependencies with a computed module ID, like `require('./locales/' + lang)`.
+        contexts: Vec<RequireContext>,
     },
     /// A JSON source file on disk.
     JSON {
@@ -64,14 +84,14 @@ impl SourceFile {
 }
 
 /// A Module.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ModuleRecord {
     pub file: SourceFile,
     /// A unique ID for this module.
     pub id: u32,
     /// Whether this module is an entry point to the graph.
     pub entry: bool,
-    /// Map of dependency names to ModuleRecords.
+    /// Map of dependency names to the modules they point to.
     pub dependencies: Dependencies,
 }
 
@@ -89,11 +109,14 @@ impl ModuleRecord {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Dependency {
     pub name: String,
     pub resolved: Option<PathBuf>,
-    pub record: Option<Rc<ModuleRecord>>,
+    /// ID of the module this dependency points to, once it has been linked.
+    pub id: Option<u32>,
+    /// Whether this dependency is loaded on demand, so it can be split into a separate chunk.
+    pub lazy: bool,
 }
 
 impl Dependency {
@@ -101,7 +124,8 @@ impl Dependency {
         Dependency {
             name,
             resolved: None,
-            record: None,
+            id: None,
+            lazy: false,
         }
     }
 
@@ -109,19 +133,297 @@ impl Dependency {
         Dependency {
             name,
             resolved: Some(resolved),
-            record: None,
+            id: None,
+            lazy: false,
         }
     }
 
-    pub fn with_record(mut self, record: &Rc<ModuleRecord>) -> Self {
-        self.set_record(record);
+    pub fn lazy(mut self) -> Self {
+        self.lazy = true;
         self
     }
+}
+
+/// Keeps track of modules.
+///
+/// Records are stored in an arena indexed by module ID, and dependencies point to other
+/// records by ID, so records can be changed or removed after they are added.
+///
+/// The map also keeps a reverse index from modules to the modules that require them. It is
+/// updated by `insert`, `remove` and `set_dependency_id`; changing dependency IDs through `get_mut` or
+/// `values_mut` bypasses it.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleMap {
+    /// Records indexed by module ID.
+    records: Vec<Option<ModuleRecord>>,
+    /// Map file paths to module IDs.
+    ids_by_path: HashMap<PathBuf, u32>,
+    /// Map module IDs to the IDs of the modules that require them.
+    dependents: HashMap<u32, BTreeSet<u32>>,
+    len: usize,
+}
+
+impl ModuleMap {
+    pub fn new() -> ModuleMap {
+        ModuleMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a record, replacing the record with the same ID if there is one.
+    pub fn insert(&mut self, record: ModuleRecord) -> Option<ModuleRecord> {
+        let index = record.id as usize;
+        if self.records.len() <= index {
+            self.records.resize(index + 1, None);
+        }
+        self.ids_by_path.insert(record.file.path().clone(), record.id);
+        let previous = self.records[index].take();
+        if let Some(ref previous) = previous {
+            if previous.file.path() != record.file.path() {
+                self.ids_by_path.remove(previous.file.path());
+            }
+            self.remove_dependents(previous);
+        } else {
+            self.len += 1;
+        }
+        for dep_id in record.dependencies.values().filter_map(|dependency| dependency.id) {
+            self.dependents.entry(dep_id).or_insert_with(BTreeSet::new).insert(record.id);
+        }
+        self.records[index] = Some(record);
+        previous
+    }
+
+    /// Remove a record. Dependencies that point to it are not changed.
+    pub fn remove(&mut self, id: u32) -> Option<ModuleRecord> {
+        let record = self.records.get_mut(id as usize).and_then(|slot| slot.take())?;
+        self.ids_by_path.remove(record.file.path());
+        self.remove_dependents(&record);
+        self.len -= 1;
+        Some(record)
+    }
+
+    /// Remove a record's dependencies from the reverse index.
+    fn remove_dependents(&mut self, record: &ModuleRecord) -> () {
+        for dep_id in record.dependencies.values().filter_map(|dependency| dependency.id) {
+            if let Some(ids) = self.dependents.get_mut(&dep_id) {
+                ids.remove(&record.id);
+            }
+        }
+    }
+
+    /// Point the dependency `name` of module `id` to the module `dep_id`.
+    pub fn set_dependency_id(&mut self, id: u32, name: &str, dep_id: Option<u32>) -> () {
+        let previous = {
+            let dependency = match self.get_mut(id).and_then(|record| record.dependencies.get_mut(name)) {
+                Some(dependency) => dependency,
+                None => return,
+            };
+            let previous = dependency.id;
+            dependency.id = dep_id;
+            previous
+        };
+        if let Some(previous) = previous {
+            // Another dependency of the same module may still point to it.
+            let still_required = self.get(id).unwrap().dependencies.values()
+                .any(|dependency| dependency.id == Some(previous));
+            if !still_required {
+                if let Some(ids) = self.dependents.get_mut(&previous) {
+                    ids.remove(&id);
+                }
+            }
+        }
+        if let Some(dep_id) = dep_id {
+            self.dependents.entry(dep_id).or_insert_with(BTreeSet::new).insert(id);
+        }
+    }
+
+    /// Get the IDs of the modules that require a module, sorted by ID.
+    ///
+    /// Dependencies that complete a cycle are included.
+    pub fn dependent_ids(&self, id: u32) -> Vec<u32> {
+        self.dependents.get(&id)
+            .map_or_else(Vec::new, |ids| ids.iter().cloned().collect())
+    }
+
+    /// Get the modules that require a module, sorted by ID.
+    pub fn dependents(&self, id: u32) -> Vec<&ModuleRecord> {
+        self.dependent_ids(id).into_iter()
+            .filter_map(|dependent| self.get(dependent))
+            .collect()
+    }
 
-    pub fn set_record(&mut self, record: &Rc<ModuleRecord>) -> () {
-        self.record = Some(Rc::clone(record));
+    pub fn get(&self, id: u32) -> Option<&ModuleRecord> {
+        self.records.get(id as usize).and_then(|slot| slot.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut ModuleRecord> {
+        self.records.get_mut(id as usize).and_then(|slot| slot.as_mut())
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Get the ID of the module for a file.
+    pub fn id_of(&self, path: &Path) -> Option<u32> {
+        self.ids_by_path.get(path).cloned()
+    }
+
+    /// Iterate over all records, sorted by ID.
+    pub fn values(&self) -> impl Iterator<Item = &ModuleRecord> {
+        self.records.iter().filter_map(|slot| slot.as_ref())
+    }
+
+    /// Iterate over all records by value, sorted by ID.
+    pub fn into_values(self) -> impl Iterator<Item = ModuleRecord> {
+        self.records.into_iter().filter_map(|slot| slot)
+    }
+
+    /// Iterate over all records mutably, sorted by ID.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut ModuleRecord> {
+        self.records.iter_mut().filter_map(|slot| slot.as_mut())
     }
 }
 
-/// Keeps track of modules.
-pub type ModuleMap = HashMap<String, Rc<ModuleRecord>>;
+/// Find groups of modules that depend on each other, directly or indirectly.
+///
+/// Every module is part of exactly one component. Modules that are not part of a cycle are
+/// in a component by themselves. Components are returned in reverse topological order, ie.
+/// a component comes after all the components it depends on.
+pub fn strongly_connected_components(modules: &ModuleMap) -> Vec<Vec<u32>> {
+    let successors = |id: u32| modules.get(id).unwrap().dependencies.values()
+        .filter_map(|dependency| dependency.id)
+        .filter(|&dep_id| modules.contains(dep_id))
+        .collect::<Vec<u32>>();
+    let ids = modules.values().map(|record| record.id).collect::<Vec<u32>>();
+
+    // Tarjan's algorithm, with an explicit call stack so deep graphs can't overflow the stack.
+    let mut index = HashMap::new();
+    let mut lowlink = HashMap::new();
+    let mut stack = vec![];
+    let mut on_stack = HashSet::new();
+    let mut components = vec![];
+
+    for root in ids {
+        if index.contains_key(&root) {
+            continue
+        }
+        // Triples of a module ID, its successors, and the next successor to visit.
+        let mut call_stack = vec![];
+        let mut visit = Some(root);
+        loop {
+            if let Some(id) = visit.take() {
+                let next_index = index.len();
+                index.insert(id, next_index);
+                lowlink.insert(id, next_index);
+                stack.push(id);
+                on_stack.insert(id);
+                call_stack.push((id, successors(id), 0));
+            }
+
+            let (id, next) = {
+                let frame = call_stack.last_mut().unwrap();
+                let next = frame.1.get(frame.2).cloned();
+                frame.2 += 1;
+                (frame.0, next)
+            };
+            match next {
+                Some(dep_id) => {
+                    if !index.contains_key(&dep_id) {
+                        visit = Some(dep_id);
+                    } else if on_stack.contains(&dep_id) {
+                        let low = min(lowlink[&id], index[&dep_id]);
+                        lowlink.insert(id, low);
+                    }
+                },
+                None => {
+                    call_stack.pop();
+                    if let Some(&(parent, _, _)) = call_stack.last() {
+                        let low = min(lowlink[&parent], lowlink[&id]);
+                        lowlink.insert(parent, low);
+                    }
+                    if lowlink[&id] == index[&id] {
+                        let mut component = vec![];
+                        loop {
+                            let member = stack.pop().unwrap();
+                            on_stack.remove(&member);
+                            component.push(member);
+                            if member == id { break }
+                        }
+                        component.sort();
+                        components.push(component);
+                    }
+                    if call_stack.is_empty() {
+                        break
+                    }
+                },
+            }
+        }
+    }
+    components
+}
+
+/// Sort module IDs so that every module comes after the modules it depends on.
+///
+/// Modules that are part of a cycle are kept together, sorted by ID. The order only depends
+/// on the shape of the graph and the module IDs, so it is stable between builds as long as
+/// the graph does not change.
+pub fn topological_order(modules: &ModuleMap) -> Vec<u32> {
+    strongly_connected_components(modules).into_iter()
+        .flat_map(|component| component)
+        .collect()
+}
+
+/// Find modules that are part of a cycle.
+/// Returns a map from module IDs to the index of the cycle they are part of.
+pub fn cycles(modules: &ModuleMap) -> HashMap<u32, usize> {
+    strongly_connected_components(modules).into_iter()
+        .filter(|component| component.len() > 1 || modules.get(component[0]).unwrap().dependencies.values()
+                .any(|dependency| dependency.id == Some(component[0])))
+        .enumerate()
+        .flat_map(|(index, component)| component.into_iter().map(move |id| (id, index)))
+        .collect()
+}
+
+/// Get the name of the npm package that contains a file, if it is inside `node_modules`.
+pub fn package_name(path: &Path) -> Option<String> {
+    let components = path.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    let start = components.iter().rposition(|component| component == "node_modules")? + 1;
+    let name = components.get(start)?;
+    if name.starts_with('@') {
+        components.get(start + 1).map(|rest| format!("{}/{}", name, rest))
+    } else {
+        Some(name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::Deps;
+
+    #[test]
+    fn keeps_track_of_dependents() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/module-exports/index.js").unwrap();
+        let mut modules = (*deps).clone();
+        let index = modules.values().find(|record| record.entry).unwrap().id;
+        let double = modules.values().find(|record| record.file.path().ends_with("double.js")).unwrap().id;
+        assert_eq!(modules.dependent_ids(double), vec![index]);
+
+        let record = modules.remove(index).unwrap();
+        assert!(modules.dependent_ids(double).is_empty());
+        modules.insert(record);
+        assert_eq!(modules.dependent_ids(double), vec![index]);
+
+        modules.set_dependency_id(index, "./double", None);
+        assert!(modules.dependent_ids(double).is_empty());
+    }
+}
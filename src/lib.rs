@@ -0,0 +1,47 @@
+//! Bundle CommonJS modules for the browser, browserify-style.
+//!
+//! Most programs only need the `Bundler`. The modules expose the individual steps: `deps`
+//! builds the module graph, `pack` writes bundles, and `visualize`, `why` and `serialize`
+//! inspect the graph.
+
+extern crate digest;
+extern crate easter;
+extern crate esprit;
+extern crate futures;
+extern crate futures_cpupool;
+extern crate glob;
+extern crate joker;
+#[macro_use] extern crate log;
+extern crate node_resolve;
+extern crate rayon;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
+extern crate sha1;
+extern crate estree_detect_requires;
+extern crate node_core_shims;
+
+mod async_build;
+pub mod builtins;
+mod bundler;
+pub mod cache;
+mod context;
+pub mod deps;
+mod esm;
+pub mod error;
+pub mod events;
+mod factor;
+mod globals;
+pub mod graph;
+pub mod loader;
+pub mod pack;
+mod package;
+pub mod serialize;
+pub mod transform;
+pub mod visualize;
+pub mod why;
+
+pub use async_build::BuildFuture;
+pub use bundler::{Bundle, Bundler, Output, OutputKind};
+pub use error::{Error, Result};
+pub use events::{Event, Listener};
@@ -2,14 +2,19 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::fs::File;
 use std::io::{Read, BufReader};
-use std::path::PathBuf;
-use esprit::script;
+use std::path::{Path, PathBuf};
+use easter::stmt::Script;
+use esprit::{module, script};
 use esprit::error::Error as EspritError;
-use estree_detect_requires::detect;
-use quicli::prelude::Result; // TODO use `failure`?
+use estree_detect_requires::{detect, detect_contexts, detect_free_variables, detect_lazy};
 use serde_json;
 use sha1::{Sha1, Digest};
+use error::{Error, Result};
+use esm::to_commonjs;
+use events::{Event, Listeners};
+use globals::insert_globals;
 use graph::{Hash, SourceFile};
+use transform::Transforms;
 
 #[derive(Debug)]
 pub struct ParseError {
@@ -22,13 +27,13 @@ impl ParseError {
         ParseError { filename: filename.clone(), inner }
     }
 
-    fn into_inner(self) -> EspritError {
-        self.inner
+    /// The file that could not be parsed.
+    pub fn filename(&self) -> &Path {
+        &self.filename
     }
-}
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    /// The line and column where the error occurred, if known.
+    pub fn location(&self) -> Option<(usize, usize)> {
         let position = match self.inner {
             EspritError::UnexpectedToken(ref token) | EspritError::FailedASI(ref token) |
             EspritError::IllegalBreak(ref token) | EspritError::IllegalContinue(ref token) |
@@ -49,10 +54,18 @@ impl fmt::Display for ParseError {
             EspritError::ExportInScript(ref _export) => None, // For now
             EspritError::CompoundParamWithUseStrict(ref _patt) => None, // For now
         };
-        write!(f, "Parse error in {}:{}\n{}", &self.filename.to_string_lossy(), match position {
-            Some(span) => format!("{}:{}", span.start.line, span.start.column),
-            None => "0:0".into(),
-        }, self.description())
+        position.map(|span| (span.start.line as usize, span.start.column as usize))
+    }
+
+    pub fn into_inner(self) -> EspritError {
+        self.inner
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (line, column) = self.location().unwrap_or((0, 0));
+        write!(f, "Parse error in {}:{}:{}\n{}", &self.filename.to_string_lossy(), line, column, self.description())
     }
 }
 
@@ -65,56 +78,81 @@ impl StdError for ParseError {
     }
 }
 
-trait Transform {
-    fn transform(&self, file: SourceFile) -> Result<SourceFile>;
-}
-
-/// Transform JSON files into CommonJS modules.
-struct JSONTransform;
-impl Transform for JSONTransform {
-    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
-        match file {
-            SourceFile::CJS { .. } => Ok(file),
-            SourceFile::JSON { path, source, hash, .. } => Ok(SourceFile::CJS {
-                path,
-                source: format!("module.exports = {}", &source),
-                hash,
-                ast: None,
-                dependencies: vec![],
-            }),
-        }
-    }
-}
-
 pub struct LoadFile {
     path: PathBuf,
-    transforms: Vec<Box<Transform>>,
+    transforms: Transforms,
+    listeners: Listeners,
+    is_module: bool,
+    include_builtins: bool,
 }
 
 impl LoadFile {
     pub fn new(path: PathBuf) -> Self {
         LoadFile {
             path,
-            transforms: vec![Box::new(JSONTransform)],
+            transforms: Transforms::new(),
+            listeners: Listeners::new(),
+            is_module: false,
+            include_builtins: true,
         }
     }
 
+    /// Parse the file as an ES module. Files are also parsed as ES modules if they can not be
+    /// parsed as a script because they contain `import` or `export` declarations.
+    pub fn as_module(mut self, is_module: bool) -> Self {
+        self.is_module = is_module;
+        self
+    }
+
+    /// Toggle inserting the `process` and `Buffer` globals from the builtin module shims.
+    /// `__filename`, `__dirname` and `global` are always inserted when they are used.
+    pub fn include_builtins(mut self, include: bool) -> Self {
+        self.include_builtins = include;
+        self
+    }
+
+    /// Apply `transforms` to the file before parsing it.
+    pub fn with_transforms(mut self, transforms: Transforms) -> Self {
+        self.transforms = transforms;
+        self
+    }
+
+    /// Send build events to `listeners`.
+    pub fn with_listeners(mut self, listeners: Listeners) -> Self {
+        self.listeners = listeners;
+        self
+    }
+
     pub fn run(&self) -> Result<SourceFile> {
-        self.read_file()
-            .and_then(|file| self.transform(file))
+        self.read_source()
+            .and_then(|(source, hash)| self.run_source(source, hash))
     }
 
-    fn read_file(&self) -> Result<SourceFile> {
-        let file = File::open(&self.path)?;
+    /// Parse and transform source code that was read using `read_source`.
+    pub fn run_source(&self, source: String, hash: Hash) -> Result<SourceFile> {
+        let source = self.transforms.apply(&self.path, source, &self.listeners)?;
+        self.parse_file(source, hash)
+            .map(|file| self.json_to_cjs(file))
+    }
+
+    /// Read the file contents and compute their hash.
+    pub fn read_source(&self) -> Result<(String, Hash)> {
+        let file = File::open(&self.path)
+            .map_err(|err| Error::io(&self.path, err))?;
         let mut reader = BufReader::new(file);
         let mut source = String::new();
-        reader.read_to_string(&mut source)?;
+        reader.read_to_string(&mut source)
+            .map_err(|err| Error::io(&self.path, err))?;
 
         let hash = Sha1::digest_str(&source) as Hash;
+        Ok((source, hash))
+    }
 
+    fn parse_file(&self, source: String, hash: Hash) -> Result<SourceFile> {
         let is_json = self.path.extension().map_or(false, |ext| ext == "json");
         if is_json {
-            let value = serde_json::from_str(&source)?;
+            let value = serde_json::from_str(&source)
+                .map_err(|err| Error::json(&self.path, err))?;
             Ok(SourceFile::JSON {
                 path: self.path.clone(),
                 source,
@@ -122,23 +160,67 @@ impl LoadFile {
                 value,
             })
         } else {
-            let ast = script(&source)
-                .map_err(|e| ParseError::new(&self.path, e))?;
-            let dependencies = detect(&ast);
+            let (source, ast) = self.parse_script(source)?;
+            let mut dependencies = detect(&ast);
+            let lazy_dependencies = detect_lazy(&ast);
+            let contexts = detect_contexts(&ast);
+            let free_variables = detect_free_variables(&ast);
+            let source = match insert_globals(&self.path, &source, &free_variables, self.include_builtins) {
+                Some((wrapped, required)) => {
+                    for name in required {
+                        if !dependencies.contains(&name) {
+                            dependencies.push(name);
+                        }
+                    }
+                    wrapped
+                },
+                None => source,
+            };
             Ok(SourceFile::CJS {
                 path: self.path.clone(),
                 source,
                 hash,
                 ast: Some(ast),
                 dependencies,
+                lazy_dependencies,
+                contexts,
             })
         }
     }
 
-    fn transform(&self, file: SourceFile) -> Result<SourceFile> {
-        self.transforms.iter()
-            .fold(Ok(file), |res, transform| {
-                res.and_then(|file| transform.transform(file))
-            })
+    /// Parse javascript source code, converting ES modules to CommonJS first.
+    fn parse_script(&self, source: String) -> Result<(String, Script)> {
+        if !self.is_module {
+            match script(&source) {
+                Ok(ast) => return Ok((source, ast)),
+                Err(EspritError::ImportInScript(_)) | Err(EspritError::ExportInScript(_)) => (),
+                Err(err) => return Err(ParseError::new(&self.path, err).into()),
+            }
+        }
+        let parsed = module(&source)
+            .map_err(|e| ParseError::new(&self.path, e))?;
+        let source = to_commonjs(&source, &parsed);
+        let ast = script(&source)
+            .map_err(|e| ParseError::new(&self.path, e))?;
+        Ok((source, ast))
+    }
+
+    /// Turn JSON files into CommonJS modules.
+    fn json_to_cjs(&self, file: SourceFile) -> SourceFile {
+        match file {
+            SourceFile::CJS { .. } => file,
+            SourceFile::JSON { path, source, hash, .. } => {
+                self.listeners.emit(Event::TransformApplied { path: &path, transform: "json" });
+                SourceFile::CJS {
+                    path,
+                    source: format!("module.exports = {}", &source),
+                    hash,
+                    ast: None,
+                    dependencies: vec![],
+                    lazy_dependencies: vec![],
+                    contexts: vec![],
+                }
+            },
+        }
     }
 }
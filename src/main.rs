@@ -1,45 +1,191 @@
-extern crate digest;
-extern crate easter;
-extern crate esprit;
-extern crate node_resolve;
-extern crate serde_json;
-extern crate sha1;
-extern crate estree_detect_requires;
-extern crate node_core_shims;
+extern crate js_bundler;
+extern crate shlex;
 extern crate time;
 #[macro_use] extern crate quicli;
 
-mod builtins;
-mod deps;
-mod graph;
-mod loader;
-mod pack;
-
-use std::io::{Write, stdout};
+use std::fs::{File, create_dir_all};
+use std::env;
+use std::io::{BufReader, BufWriter, Write, stdout};
+use std::path::PathBuf;
 use time::PreciseTime;
 use quicli::prelude::*;
-use deps::Deps;
-use pack::Pack;
+use js_bundler::{Bundler, Output, OutputKind};
+use js_bundler::cache::Cache;
+use js_bundler::pack::IdStrategy;
+use js_bundler::serialize::{read_graph, write_graph};
+use js_bundler::transform::{CommandTransform, TransformRule};
+use js_bundler::visualize::{GraphFormat, Visualize};
+use js_bundler::why::{describe_chains, why_included};
 
 #[derive(Debug, StructOpt)]
 struct Options {
-    entry: String,
+    entries: Vec<String>,
     #[structopt(long = "no-builtins", help = "Exclude shims for builtin modules. Useful when generating a bundle for Node.")]
     no_builtins: bool,
+    #[structopt(long = "chunk-dir", help = "Split modules loaded with require.ensure() into chunk files in this directory.", parse(from_os_str))]
+    chunk_dir: Option<PathBuf>,
+    #[structopt(long = "public-path", help = "URL prefix that chunk files are loaded from.", default_value = "")]
+    public_path: String,
+    #[structopt(long = "factor-dir", help = "Write a bundle for each entry point, and a common.js bundle for modules shared between them, to this directory.", parse(from_os_str))]
+    factor_dir: Option<PathBuf>,
+    #[structopt(long = "vendor-dir", help = "Write modules from node_modules to vendor.js, and all other modules to app.js, in this directory.", parse(from_os_str))]
+    vendor_dir: Option<PathBuf>,
+    #[structopt(long = "no-dedupe", help = "Include modules with identical source code multiple times.")]
+    no_dedupe: bool,
+    #[structopt(long = "ids", help = "How to generate module IDs: sequential, hashed, or path.", default_value = "sequential")]
+    ids: IdStrategy,
+    #[structopt(long = "dependency-order", help = "Write modules after the modules they depend on, instead of sorting them by content.")]
+    dependency_order: bool,
+    #[structopt(long = "cache-dir", help = "Cache loaded files in this directory, to speed up later builds.", parse(from_os_str))]
+    cache_dir: Option<PathBuf>,
+    #[structopt(long = "from-graph", help = "Start from a module graph that was written by the dump command.", parse(from_os_str))]
+    from_graph: Option<PathBuf>,
+    #[structopt(long = "transform", help = "Pipe the source of every file through this command before parsing it, like \"babel --presets env\". Arguments are split like in a shell, so they can be quoted. Can be repeated.")]
+    transforms: Vec<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(name = "graph", about = "Write the dependency graph instead of a bundle.")]
+    Graph {
+        entries: Vec<String>,
+        #[structopt(long = "format", help = "Output format: dot or mermaid.", default_value = "dot")]
+        format: GraphFormat,
+        #[structopt(long = "collapse-packages", help = "Show all modules from an npm package as a single node.")]
+        collapse_packages: bool,
+        #[structopt(long = "highlight-cycles", help = "Color modules and dependencies that are part of a cycle.")]
+        highlight_cycles: bool,
+    },
+    #[structopt(name = "why", about = "Show the shortest chains of require() calls that include a module.")]
+    Why {
+        #[structopt(help = "Path to a module, or the name of an npm package.")]
+        target: String,
+        entries: Vec<String>,
+    },
+    #[structopt(name = "dump", about = "Write the module graph as JSON, so it can be loaded with --from-graph.")]
+    Dump {
+        entries: Vec<String>,
+    },
 }
 
 main!(|args: Options| {
     let start = PreciseTime::now();
-    let mut deps = Deps::new()
+    let mut bundler = Bundler::new()
         .include_builtins(!args.no_builtins)
         .with_builtins_path("./crates/node-core-shims".into());
+    if let Some(cache_dir) = args.cache_dir {
+        bundler = bundler.with_cache(Cache::new(cache_dir)?);
+    }
+    for transform in &args.transforms {
+        // Split like a shell would, so arguments can contain quoted spaces.
+        let words = shlex::split(transform)
+            .ok_or_else(|| js_bundler::Error::InvalidInput(format!("Could not parse --transform \"{}\": unbalanced quotes", transform)))?;
+        if let Some((program, args)) = words.split_first() {
+            bundler = bundler.with_transform(TransformRule::new(CommandTransform::new(program).with_args(args)));
+        }
+    }
+    if let Some(ref graph_path) = args.from_graph {
+        bundler = bundler.with_modules(read_graph(BufReader::new(File::open(graph_path)?))?);
+    }
 
-    deps.run(&args.entry)?;
-    let mut out = stdout();
-    let num_modules = deps.len();
-    let bundle = Pack::new(&deps).to_string();
-    let size = bundle.len();
-    out.write_all(bundle.as_bytes())?;
-    let end = PreciseTime::now();
-    eprint!("wrote {} bytes containing {} modules, took {}ms\n", size, num_modules, start.to(end).num_milliseconds());
+    match args.command {
+        Some(Command::Graph { ref entries, format, collapse_packages, highlight_cycles }) => {
+            let deps = bundler.with_entries(entries).graph()?;
+            let graph = Visualize::new(&deps)
+                .with_format(format)
+                .collapse_packages(collapse_packages)
+                .highlight_cycles(highlight_cycles)
+                .to_string();
+            stdout().write_all(graph.as_bytes())?;
+            return Ok(())
+        },
+        Some(Command::Why { ref target, ref entries }) => {
+            let deps = bundler.with_entries(entries).graph()?;
+            let chains = why_included(&deps, target);
+            if chains.is_empty() {
+                eprint!("{} is not included in the bundle\n", target);
+            } else {
+                let root = env::current_dir()?;
+                stdout().write_all(describe_chains(&deps, &chains, &root).as_bytes())?;
+            }
+            return Ok(())
+        },
+        Some(Command::Dump { ref entries }) => {
+            let deps = bundler.with_entries(entries).graph()?;
+            write_graph(&deps, BufWriter::new(stdout()))?;
+            return Ok(())
+        },
+        None => (),
+    }
+
+    let output = if args.factor_dir.is_some() {
+        OutputKind::Factored
+    } else if args.vendor_dir.is_some() {
+        OutputKind::Vendor
+    } else if args.chunk_dir.is_some() {
+        OutputKind::Chunks
+    } else {
+        OutputKind::Single
+    };
+    let bundle = bundler
+        .with_entries(&args.entries)
+        .with_output(output)
+        .with_public_path(args.public_path)
+        .dedupe(!args.no_dedupe)
+        .with_ids(args.ids)
+        .dependency_order(args.dependency_order)
+        .build()?;
+    let num_modules = bundle.graph.len();
+    if bundle.dedupe_savings > 0 {
+        eprint!("deduplicated identical modules, saving {} bytes\n", bundle.dedupe_savings);
+    }
+
+    match bundle.output {
+        Output::Factored(factored) => {
+            let factor_dir = args.factor_dir.unwrap();
+            create_dir_all(&factor_dir)?;
+            File::create(factor_dir.join("common.js"))?
+                .write_all(factored.common.as_bytes())?;
+            for entry in &factored.entries {
+                File::create(factor_dir.join(&entry.filename))?
+                    .write_all(entry.source.as_bytes())?;
+            }
+            let end = PreciseTime::now();
+            eprint!("wrote {} entry bundles and common.js containing {} modules to {}, took {}ms\n",
+                    factored.entries.len(), num_modules, factor_dir.to_string_lossy(), start.to(end).num_milliseconds());
+        },
+        Output::Vendor(split) => {
+            let vendor_dir = args.vendor_dir.unwrap();
+            create_dir_all(&vendor_dir)?;
+            File::create(vendor_dir.join("vendor.js"))?
+                .write_all(split.vendor.as_bytes())?;
+            File::create(vendor_dir.join("app.js"))?
+                .write_all(split.app.as_bytes())?;
+            let end = PreciseTime::now();
+            eprint!("wrote vendor.js and app.js containing {} modules to {}, took {}ms\n",
+                    num_modules, vendor_dir.to_string_lossy(), start.to(end).num_milliseconds());
+        },
+        Output::Chunks(split) => {
+            let chunk_dir = args.chunk_dir.unwrap();
+            create_dir_all(&chunk_dir)?;
+            for chunk in &split.chunks {
+                File::create(chunk_dir.join(&chunk.filename))?
+                    .write_all(chunk.source.as_bytes())?;
+            }
+            File::create(chunk_dir.join("manifest.json"))?
+                .write_all(split.manifest.as_bytes())?;
+            eprint!("wrote {} chunks to {}\n", split.chunks.len(), chunk_dir.to_string_lossy());
+            write_stdout(&split.main, num_modules, start)?;
+        },
+        Output::Single(source) => write_stdout(&source, num_modules, start)?,
+    }
 });
+
+fn write_stdout(bundle: &str, num_modules: usize, start: PreciseTime) -> Result<()> {
+    stdout().write_all(bundle.as_bytes())?;
+    let end = PreciseTime::now();
+    eprint!("wrote {} bytes containing {} modules, took {}ms\n", bundle.len(), num_modules, start.to(end).num_milliseconds());
+    Ok(())
+}
@@ -1,52 +1,673 @@
-use std::collections::BTreeMap;
-use std::rc::Rc;
-use serde_json;
-use graph::{ModuleMap, ModuleRecord};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use serde_json::{self, Value};
+use sha1::{Sha1, Digest};
+use factor::factor;
+use graph::{Hash, ModuleMap, ModuleRecord, package_name, topological_order};
+
+/// Maps internal module IDs to the IDs that are written to the bundle.
+type OutputIds = HashMap<u32, Value>;
+
+/// How to generate the module IDs that are written to the bundle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdStrategy {
+    /// Number modules in the order they were found. Short, but adding a single require()
+    /// call can change the IDs of many modules.
+    Sequential,
+    /// Use a short hash of the module path relative to the project root. IDs only change
+    /// when the module is moved.
+    Hashed,
+    /// Use the module path relative to the project root. Useful for debugging.
+    Path,
+}
+
+impl FromStr for IdStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(IdStrategy::Sequential),
+            "hashed" => Ok(IdStrategy::Hashed),
+            "path" => Ok(IdStrategy::Path),
+            _ => Err(format!("Unknown module ID strategy \"{}\", expected one of sequential, hashed, path", s)),
+        }
+    }
+}
+
+/// A part of the bundle that is loaded on demand.
+pub struct Chunk {
+    /// A unique ID for this chunk.
+    pub id: u32,
+    /// File name for this chunk.
+    pub filename: String,
+    /// The javascript source for this chunk.
+    pub source: String,
+}
+
+/// A bundle that is split into a main file and lazily loaded chunks.
+pub struct SplitBundle {
+    /// The main bundle, containing the runtime and everything the entry points need
+    /// synchronously.
+    pub main: String,
+    /// Chunks that are loaded on demand by `require.ensure()`.
+    pub chunks: Vec<Chunk>,
+    /// JSON object mapping chunk IDs to file names.
+    pub manifest: String,
+}
+
+/// A bundle for a single entry point, see `Pack::to_factored`.
+pub struct EntryBundle {
+    /// ID of the entry module.
+    pub id: u32,
+    /// File name for this bundle.
+    pub filename: String,
+    /// The javascript source for this bundle.
+    pub source: String,
+}
+
+/// Bundles for multiple entry points, with shared modules factored out into a common bundle.
+pub struct FactoredBundle {
+    /// Modules that are used by more than one entry point.
+    /// This must be loaded before any of the entry bundles.
+    pub common: String,
+    /// Bundles containing the modules that are exclusive to each entry point.
+    pub entries: Vec<EntryBundle>,
+}
+
+/// Bundles for application code and for code from `node_modules`, see `Pack::to_vendor_split`.
+pub struct VendorBundle {
+    /// Modules from `node_modules`.
+    /// This must be loaded before the app bundle.
+    pub vendor: String,
+    /// All other modules.
+    pub app: String,
+}
 
 /// Pack a `ModuleMap` into a browserify-style javascript bundle.
 pub struct Pack<'a> {
     modules: &'a ModuleMap,
+    public_path: String,
+    root: PathBuf,
+    dedupe: bool,
+    id_strategy: IdStrategy,
+    dependency_order: bool,
 }
 
 impl<'a> Pack<'a> {
     pub fn new(modules: &ModuleMap) -> Pack {
-        Pack { modules }
+        Pack {
+            modules,
+            public_path: String::new(),
+            root: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            dedupe: true,
+            id_strategy: IdStrategy::Sequential,
+            dependency_order: false,
+        }
+    }
+
+    /// Set the project root directory. Hashed and path module IDs are based on paths relative
+    /// to this directory. Defaults to the current working directory.
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = root;
+        self
+    }
+
+    /// Set the URL that chunk files are loaded from at runtime.
+    /// Defaults to the empty string, ie. relative to the current page.
+    pub fn with_public_path(mut self, public_path: String) -> Self {
+        self.public_path = public_path;
+        self
+    }
+
+    /// Toggle deduplication of modules with identical source code. Enabled by default.
+    /// If `true`, only one copy of the source code is included, and the other modules call
+    /// into it with their own `require` function, like browserify's dedupe.
+    pub fn dedupe(mut self, dedupe: bool) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    /// Set how module IDs are generated. Defaults to `IdStrategy::Sequential`.
+    pub fn with_ids(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
     }
 
+    /// Toggle writing modules in dependency order. Disabled by default.
+    /// If `true`, every module is written after the modules it depends on, so the bundle is
+    /// easier to read. Otherwise modules are sorted by content hash, which compresses better.
+    pub fn dependency_order(mut self, dependency_order: bool) -> Self {
+        self.dependency_order = dependency_order;
+        self
+    }
+
+    /// Pack all modules into a single bundle.
     pub fn to_string(&self) -> String {
-        let mut string = String::from("_require = ");
-        string.push_str(include_str!("./runtime.js"));
-        string.push_str("({\n");
+        let modules = self.sorted_modules();
+        let ids = self.output_ids(self.id_strategy, &modules);
+        let entries = entry_ids(&modules);
+        self.write_bundle("_require", &modules, &ids, &entries, None)
+    }
+
+    /// Pack modules into a main bundle and a chunk for each `require.ensure()` split point.
+    ///
+    /// Dynamic `import()` is not supported as a split point, because esprit can not parse it.
+    pub fn to_split_bundle(&self) -> SplitBundle {
+        let modules = self.sorted_modules();
+        let ids = self.output_ids(self.id_strategy, &modules);
+        let entries = entry_ids(&modules);
+        let chunk_of = assign_chunks(&modules);
+
+        let mut chunk_ids = chunk_of.values().cloned().filter(|&id| id != 0).collect::<Vec<u32>>();
+        chunk_ids.sort();
+        chunk_ids.dedup();
+
+        let files = chunk_ids.iter()
+            .map(|&id| (id, format!("chunk.{}.js", id)))
+            .collect::<BTreeMap<u32, String>>();
+        let urls = files.iter()
+            .map(|(&id, filename)| (id, format!("{}{}", self.public_path, filename)))
+            .collect::<BTreeMap<u32, String>>();
+        let ensure_chunks = ensure_chunks(&modules, &chunk_of).into_iter()
+            .map(|(id, chunks)| (id_key(&ids[&id]), chunks))
+            .collect::<BTreeMap<String, Vec<u32>>>();
+
+        let in_chunk = |chunk: u32| modules.iter()
+            .filter(|record| chunk_of[&record.id] == chunk)
+            .cloned()
+            .collect::<Vec<&ModuleRecord>>();
+
+        let chunks_arg = format!("{{\"files\":{},\"ensure\":{}}}",
+                                 serde_json::to_string(&urls).unwrap(),
+                                 serde_json::to_string(&ensure_chunks).unwrap());
+        let main = self.write_bundle("_require", &in_chunk(0), &ids, &entries, Some(&chunks_arg));
 
+        let chunks = chunk_ids.iter()
+            .map(|&id| Chunk {
+                id,
+                filename: files[&id].clone(),
+                source: format!("_require.chunk({},{});", id, self.write_modules(&in_chunk(id), &ids)),
+            })
+            .collect();
+
+        SplitBundle {
+            main,
+            chunks,
+            manifest: serde_json::to_string_pretty(&files).unwrap(),
+        }
+    }
+
+    /// Pack modules into a bundle per entry point, and a common bundle for modules that are
+    /// shared by multiple entry points.
+    ///
+    /// The bundles assign their `require` function to the global `require` variable, so
+    /// the entry bundles can find modules from the common bundle through the runtime's
+    /// `previousRequire` fallback.
+    pub fn to_factored(&self) -> FactoredBundle {
+        let modules = self.sorted_modules();
+        let ids = self.output_ids(self.id_strategy, &modules);
+        let factored = factor(self.modules);
+        let pick = |ids: &HashSet<u32>| modules.iter()
+            .filter(|record| ids.contains(&record.id))
+            .cloned()
+            .collect::<Vec<&ModuleRecord>>();
+
+        let common = self.write_bundle("require", &pick(&factored.common), &ids, &[], None);
+
+        let mut filenames = HashSet::new();
+        let entries = factored.entries.iter()
+            .map(|&(id, ref module_ids)| {
+                let record = modules.iter().find(|record| record.id == id).unwrap();
+                let stem = record.file.path().file_stem()
+                    .map_or("entry".to_string(), |stem| stem.to_string_lossy().into_owned());
+                let mut filename = format!("{}.js", stem);
+                if !filenames.insert(filename.clone()) {
+                    filename = format!("{}.{}.js", stem, id);
+                }
+                EntryBundle {
+                    id,
+                    filename,
+                    source: self.write_bundle("require", &pick(module_ids), &ids, &[id], None),
+                }
+            })
+            .collect();
+
+        FactoredBundle { common, entries }
+    }
+
+    /// Pack modules from `node_modules` into a vendor bundle, and all other modules into
+    /// an app bundle.
+    ///
+    /// Sequential module IDs are replaced by hashed IDs, so they do not change when unrelated
+    /// modules are added or removed. That way, the vendor bundle stays the same across builds
+    /// as long as dependencies do not change, and can be cached for a long time.
+    /// Like `to_factored`, the bundles assign their `require` function to the global `require`
+    /// variable so the app bundle can find modules from the vendor bundle.
+    pub fn to_vendor_split(&self) -> VendorBundle {
+        let modules = self.sorted_modules();
+        // Sequential IDs would change whenever a module is added to the app.
+        let id_strategy = match self.id_strategy {
+            IdStrategy::Sequential => IdStrategy::Hashed,
+            other => other,
+        };
+        let ids = self.output_ids(id_strategy, &modules);
+        let (vendor, app): (Vec<&ModuleRecord>, Vec<&ModuleRecord>) = modules.iter()
+            .cloned()
+            .partition(|record| package_name(record.file.path()).is_some());
+
+        VendorBundle {
+            vendor: self.write_bundle("require", &vendor, &ids, &[], None),
+            app: self.write_bundle("require", &app, &ids, &entry_ids(&modules), None),
+        }
+    }
+
+    /// Get the number of bytes that deduplication saves when packing all modules into a
+    /// single bundle.
+    pub fn dedupe_savings(&self) -> usize {
+        let modules = self.sorted_modules();
+        let ids = self.output_ids(self.id_strategy, &modules);
+        find_duplicates(&modules).iter()
+            .map(|(id, original)| {
+                let record = self.modules.values().find(|record| record.id == *id).unwrap();
+                record.file.source().len().saturating_sub(dedupe_stub(&ids[original]).len())
+            })
+            .sum()
+    }
+
+    /// Write a bundle that assigns its require function to the global variable `global`.
+    fn write_bundle(&self, global: &str, modules: &[&ModuleRecord], ids: &OutputIds, entries: &[u32], chunks: Option<&str>) -> String {
+        let entries = entries.iter().map(|id| &ids[id]).collect::<Vec<&Value>>();
+        format!("{global} = {runtime}({modules},{{}},{entries}{chunks});",
+                global = global,
+                runtime = include_str!("./runtime.js"),
+                modules = self.write_modules(modules, ids),
+                entries = serde_json::to_string(&entries).unwrap(),
+                chunks = chunks.map_or(String::new(), |chunks| format!(",{}", chunks)))
+    }
+
+    /// Write a javascript object containing module definitions.
+    fn write_modules(&self, modules: &[&ModuleRecord], ids: &OutputIds) -> String {
+        let duplicates = if self.dedupe { find_duplicates(modules) } else { HashMap::new() };
+        let mut string = String::from("{\n");
         let mut first = true;
-        let mut entries = vec![];
-        let mut modules: Vec<&Rc<ModuleRecord>> = self.modules.values().collect();
-        modules.sort_unstable_by(|a, b| a.hash_cmp(b));
         for record in modules {
             if !first { string.push_str(",\n"); }
+            let source = match duplicates.get(&record.id) {
+                Some(original) => dedupe_stub(&ids[original]),
+                None => record.file.source().clone(),
+            };
             string.push_str(&format!(
-                "{id}:[function(require,exports,module){{\n{source}\n}},{deps}]",
-                id = serde_json::to_string(&record.id).unwrap(),
-                source = record.file.source(),
+                "{id}:[function(require,module,exports){{\n{source}\n}},{deps}]",
+                id = serde_json::to_string(&ids[&record.id]).unwrap(),
+                source = source,
                 deps = serde_json::to_string(
                     &record.dependencies.iter()
-                        .map(|(key, val)| (key, match val.record {
-                             Some(ref rec) => Some(rec.id),
-                             None => None,
-                         }))
-                        .collect::<BTreeMap<&String, Option<u32>>>()
+                        .map(|(key, val)| (key, val.id.map(|id| &ids[&id])))
+                        .collect::<BTreeMap<&String, Option<&Value>>>()
                 ).unwrap(),
             ));
             first = false;
+        }
+        string.push_str("}");
+        string
+    }
+
+    fn output_ids(&self, id_strategy: IdStrategy, modules: &[&ModuleRecord]) -> OutputIds {
+        match id_strategy {
+            IdStrategy::Sequential => sequential_ids(modules),
+            IdStrategy::Hashed => hashed_ids(&self.root, modules),
+            IdStrategy::Path => path_ids(&self.root, modules),
+        }
+    }
+
+    fn sorted_modules(&self) -> Vec<&ModuleRecord> {
+        if self.dependency_order {
+            return topological_order(self.modules).into_iter()
+                .filter_map(|id| self.modules.get(id))
+                .collect()
+        }
+        let mut modules: Vec<&ModuleRecord> = self.modules.values().collect();
+        modules.sort_unstable_by(|a, b| a.hash_cmp(b).then(a.id.cmp(&b.id)));
+        modules
+    }
+}
+
+/// Use the internal module IDs in the bundle.
+fn sequential_ids(modules: &[&ModuleRecord]) -> OutputIds {
+    modules.iter()
+        .map(|record| (record.id, Value::from(record.id)))
+        .collect()
+}
+
+/// Use a short hash of the path relative to `root` as the module ID in the bundle.
+fn hashed_ids(root: &Path, modules: &[&ModuleRecord]) -> OutputIds {
+    let mut ids = OutputIds::new();
+    let mut used = HashSet::new();
+    for record in modules {
+        let hash = Sha1::digest_str(&relative_path(root, record.file.path()));
+        let hex = hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        // Use the full hash in the unlikely case that the short one collides.
+        let id = if used.insert(hex[..8].to_string()) { hex[..8].to_string() } else { hex };
+        ids.insert(record.id, Value::from(id));
+    }
+    ids
+}
+
+/// Use the path relative to `root` as the module ID in the bundle.
+fn path_ids(root: &Path, modules: &[&ModuleRecord]) -> OutputIds {
+    modules.iter()
+        .map(|record| (record.id, Value::from(relative_path(root, record.file.path()))))
+        .collect()
+}
 
-            if record.entry {
-                entries.push(record.id);
+/// Get a `/` separated path to `path` relative to the project root `root`.
+///
+/// Relative paths are relative to the current directory, like the module paths that come
+/// from resolving relative entry points. Paths outside of `root` are made absolute.
+pub fn relative_path(root: &Path, path: &Path) -> String {
+    let root = absolute_path(root);
+    let path = absolute_path(path);
+    match path.strip_prefix(&root) {
+        Ok(relative) => relative.components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<String>>()
+            .join("/"),
+        Err(_) => path.to_string_lossy().replace('\\', "/"),
+    }
+}
+
+/// Make a path absolute and remove `.` and `..` components, without touching the file system.
+fn absolute_path(path: &Path) -> PathBuf {
+    let path = env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => { normalized.pop(); },
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Get the string form of an output ID, for use as a JSON object key.
+fn id_key(id: &Value) -> String {
+    match *id {
+        Value::String(ref string) => string.clone(),
+        ref other => other.to_string(),
+    }
+}
+
+/// Find modules with identical source code.
+/// Returns a map from the IDs of duplicate modules to the ID of the module whose source they reuse.
+/// `modules` must be sorted, so the same module is picked as the original every time.
+fn find_duplicates(modules: &[&ModuleRecord]) -> HashMap<u32, u32> {
+    let mut originals: HashMap<&Hash, &ModuleRecord> = HashMap::new();
+    let mut duplicates = HashMap::new();
+    for &record in modules {
+        match originals.entry(record.file.hash()) {
+            Entry::Occupied(original) => {
+                if original.get().file.source() == record.file.source() {
+                    duplicates.insert(record.id, original.get().id);
+                }
+            },
+            Entry::Vacant(slot) => {
+                slot.insert(record);
+            },
+        }
+    }
+    duplicates
+}
+
+/// Source code for a duplicate module that calls into the original module's function.
+/// `arguments[4]` is the `modules` object passed in by the runtime.
+fn dedupe_stub(original: &Value) -> String {
+    format!("arguments[4][{}][0].apply(this,arguments)", serde_json::to_string(original).unwrap())
+}
+
+fn entry_ids(modules: &[&ModuleRecord]) -> Vec<u32> {
+    modules.iter()
+        .filter(|record| record.entry)
+        .map(|record| record.id)
+        .collect()
+}
+
+/// Assign modules to chunks.
+///
+/// Everything that the entry points require synchronously ends up in the main chunk, `0`.
+/// Each lazily loaded module that is not already in an earlier chunk starts a new chunk,
+/// together with its own synchronous dependencies.
+fn assign_chunks(modules: &[&ModuleRecord]) -> HashMap<u32, u32> {
+    let by_id = modules.iter()
+        .map(|record| (record.id, *record))
+        .collect::<HashMap<u32, &ModuleRecord>>();
+    let mut chunk_of = HashMap::new();
+    let mut lazy_roots = VecDeque::new();
+
+    let entries = entry_ids(modules);
+    fill_chunk(0, entries, &by_id, &mut chunk_of, &mut lazy_roots);
+
+    let mut next_chunk = 1;
+    while let Some(root) = lazy_roots.pop_front() {
+        if chunk_of.contains_key(&root) {
+            continue
+        }
+        fill_chunk(next_chunk, vec![root], &by_id, &mut chunk_of, &mut lazy_roots);
+        next_chunk += 1;
+    }
+
+    // Modules that we could not reach from an entry point stay in the main chunk.
+    for record in modules {
+        chunk_of.entry(record.id).or_insert(0);
+    }
+    chunk_of
+}
+
+fn fill_chunk(chunk: u32, roots: Vec<u32>, by_id: &HashMap<u32, &ModuleRecord>, chunk_of: &mut HashMap<u32, u32>, lazy_roots: &mut VecDeque<u32>) -> () {
+    let mut stack = roots;
+    while let Some(id) = stack.pop() {
+        if chunk_of.contains_key(&id) || !by_id.contains_key(&id) {
+            continue
+        }
+        chunk_of.insert(id, chunk);
+        for dependency in by_id[&id].dependencies.values() {
+            if let Some(dep_id) = dependency.id {
+                if dependency.lazy {
+                    lazy_roots.push_back(dep_id);
+                } else {
+                    stack.push(dep_id);
+                }
             }
         }
+    }
+}
 
-        string.push_str("},{},");
-        string.push_str(&serde_json::to_string(&entries).unwrap());
-        string.push_str(");");
-        string
+/// Find the chunks that each module's `require.ensure()` calls need, by module ID.
+///
+/// That is every chunk containing a lazy dependency of the module, or one of their synchronous
+/// dependencies, which may have been put in an earlier chunk.
+fn ensure_chunks(modules: &[&ModuleRecord], chunk_of: &HashMap<u32, u32>) -> BTreeMap<u32, Vec<u32>> {
+    let by_id = modules.iter()
+        .map(|record| (record.id, *record))
+        .collect::<HashMap<u32, &ModuleRecord>>();
+    let mut ensure_chunks = BTreeMap::new();
+    for record in modules {
+        let mut stack = record.dependencies.values()
+            .filter(|dependency| dependency.lazy)
+            .filter_map(|dependency| dependency.id)
+            .collect::<Vec<u32>>();
+        if stack.is_empty() {
+            continue
+        }
+        let mut seen = HashSet::new();
+        let mut chunks = BTreeSet::new();
+        while let Some(id) = stack.pop() {
+            if !seen.insert(id) || !by_id.contains_key(&id) {
+                continue
+            }
+            if chunk_of[&id] != 0 {
+                chunks.insert(chunk_of[&id]);
+            }
+            stack.extend(by_id[&id].dependencies.values()
+                .filter(|dependency| !dependency.lazy)
+                .filter_map(|dependency| dependency.id));
+        }
+        ensure_chunks.insert(record.id, chunks.into_iter().collect());
+    }
+    ensure_chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs::{File, create_dir_all};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use serde_json::Value;
+    use deps::Deps;
+    use super::{IdStrategy, Pack, relative_path};
+
+    /// Bundle `entry` and run the bundle in node, returning its output.
+    fn run_bundle(name: &str, entry: &str) -> String {
+        let mut deps = Deps::new();
+        deps.run(entry).unwrap();
+        let bundle = Pack::new(&deps).to_string();
+
+        let path = env::temp_dir().join(format!("js-bundler-test-{}.js", name));
+        File::create(&path).unwrap()
+            .write_all(bundle.as_bytes()).unwrap();
+        let output = Command::new("node").arg(&path).output()
+            .expect("node must be installed to run the runtime tests");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Load `entries` and get the output ID of each module, by path.
+    fn output_ids(entries: &[&str], id_strategy: IdStrategy) -> HashMap<PathBuf, Value> {
+        let mut deps = Deps::new();
+        for entry in entries {
+            deps.run(entry).unwrap();
+        }
+        let pack = Pack::new(&deps);
+        let modules = pack.sorted_modules();
+        pack.output_ids(id_strategy, &modules).into_iter()
+            .map(|(id, output_id)| (deps.get(id).unwrap().file.path().clone(), output_id))
+            .collect()
+    }
+
+    #[test]
+    fn loads_all_chunks_needed_by_a_split_point() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/ensure/index.js").unwrap();
+        let dir = env::temp_dir().join("js-bundler-test-ensure");
+        create_dir_all(&dir).unwrap();
+        let split = Pack::new(&deps)
+            .with_public_path(format!("{}/", dir.to_string_lossy()))
+            .to_split_bundle();
+        assert_eq!(split.chunks.len(), 2);
+        for chunk in &split.chunks {
+            File::create(dir.join(&chunk.filename)).unwrap()
+                .write_all(chunk.source.as_bytes()).unwrap();
+        }
+
+        // Load chunk scripts with require() instead of a <script> tag.
+        let main = format!("global.document = {{ createElement: function () {{ return {{}}; }}, \
+                            head: {{ appendChild: function (script) {{ setTimeout(function () {{ require(script.src); }}); }} }} }};\n{}",
+                           split.main);
+        let path = dir.join("main.js");
+        File::create(&path).unwrap()
+            .write_all(main.as_bytes()).unwrap();
+        let output = Command::new("node").arg(&path).output()
+            .expect("node must be installed to run the runtime tests");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "y shared\n");
+    }
+
+    #[test]
+    fn runs_module_exports() {
+        assert_eq!(run_bundle("module-exports", "./tests/fixtures/module-exports/index.js"), "42\n");
+    }
+
+    #[test]
+    fn runs_computed_requires() {
+        assert_eq!(run_bundle("context", "./tests/fixtures/context/index.js"), "one\ntwo\n");
+    }
+
+    #[test]
+    fn skips_other_files_in_computed_requires() {
+        // README.md and style.css would fail to parse if they were included.
+        assert_eq!(run_bundle("context-extensions", "./tests/fixtures/context-extensions/index.js"), "one\ntwo\n");
+    }
+
+    #[test]
+    fn cycles_see_partial_exports() {
+        // Same as running `node tests/fixtures/cycle/a.js`.
+        assert_eq!(run_bundle("cycle", "./tests/fixtures/cycle/a.js"),
+                   "{\"a\":{\"sawEarly\":\"a\",\"sawLate\":\"undefined\"},\"b\":{\"sawEarly\":\"a\",\"sawLate\":\"undefined\"}}\n");
+    }
+
+    #[test]
+    fn circular_imports_see_hoisted_functions() {
+        // Real ES modules would print "a value": imported variables are not live bindings.
+        assert_eq!(run_bundle("esm-cycle", "./tests/fixtures/esm-cycle/a.mjs"), "a undefined\n");
+    }
+
+    #[test]
+    fn writes_dependencies_first() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/module-exports/index.js").unwrap();
+        let bundle = Pack::new(&deps).dependency_order(true).to_string();
+        let position = |needle: &str| bundle.find(needle).unwrap();
+        assert!(position("return x * 2") < position("require('./double')"));
+        assert!(position("\"value\"") < position("require('./double')"));
+    }
+    #[test]
+    fn paths_are_relative_to_the_root() {
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(relative_path(&cwd, Path::new("./src/a.js")), "src/a.js");
+        assert_eq!(relative_path(Path::new("./src"), Path::new("./src/lib/../a.js")), "a.js");
+        assert_eq!(relative_path(&cwd.join("src"), &cwd.join("src/a.js")), "a.js");
+    }
+    #[test]
+    fn duplicates_get_their_own_exports() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/dedupe/index.js").unwrap();
+        assert!(Pack::new(&deps).dedupe_savings() > 0);
+        // The two counters have the same source, but each has its own state and dependencies.
+        assert_eq!(run_bundle("dedupe", "./tests/fixtures/dedupe/index.js"), "a 1 b 0\n");
+    }
+
+    #[test]
+    fn stable_ids_do_not_change_when_modules_are_added() {
+        for &id_strategy in &[IdStrategy::Hashed, IdStrategy::Path] {
+            let before = output_ids(&["./tests/fixtures/module-exports/index.js"], id_strategy);
+            let after = output_ids(&["./tests/fixtures/overlap/a.js", "./tests/fixtures/module-exports/index.js"], id_strategy);
+            assert_eq!(before.len(), 3);
+            assert_eq!(after.len(), 5);
+            for (path, id) in before {
+                assert_eq!(after[&path], id);
+            }
+        }
+    }
+
+    #[test]
+    fn vendor_bundle_does_not_change_with_the_app() {
+        let vendor_split = |entries: &[&str]| {
+            let mut deps = Deps::new();
+            for entry in entries {
+                deps.run(entry).unwrap();
+            }
+            Pack::new(&deps).to_vendor_split()
+        };
+        let before = vendor_split(&["./tests/fixtures/vendor/index.js"]);
+        let after = vendor_split(&["./tests/fixtures/vendor/index.js", "./tests/fixtures/vendor/other.js"]);
+        assert!(before.vendor.contains("hello "));
+        assert!(!before.app.contains("hello "));
+        assert_eq!(before.vendor, after.vendor);
+        assert_ne!(before.app, after.app);
     }
 }
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use node_resolve::Resolver;
+use serde_json::{self, Value};
+use error::{Error, Result};
+use transform::{CommandTransform, TransformRule, Transforms};
+
+/// Runs a browserify transform module on stdin. The module path is the first argument.
+const NODE_TRANSFORM_RUNNER: &str = "\
+var transform = require(process.argv[1]);
+var options = JSON.parse(process.env.BUNDLER_TRANSFORM_OPTIONS || '{}');
+process.stdin.pipe(transform(process.env.BUNDLER_FILE, options)).pipe(process.stdout);
+";
+
+/// What we need to know about the package that a file belongs to.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Package {
+    /// Transforms that the package declares in the `browserify.transform` field.
+    ///
+    /// They only apply to files that belong to the package, i.e. files whose nearest
+    /// package.json declared them. Files in the package's own `node_modules` belong to
+    /// other packages.
+    pub transforms: Transforms,
+    /// Whether `.js` files in the package are ES modules, because of `"type": "module"`.
+    pub is_module: bool,
+}
+
+/// Reads the nearest package.json for each file, and remembers what was in it.
+#[derive(Debug, Default)]
+pub(crate) struct Packages {
+    /// Packages for each package.json that was seen, by path.
+    packages: Mutex<HashMap<PathBuf, Package>>,
+}
+
+impl Packages {
+    pub fn new() -> Packages {
+        Packages::default()
+    }
+
+    /// Get the package that contains a file.
+    /// This may be called from multiple threads at once.
+    pub fn for_file(&self, path: &Path, resolver: &Resolver) -> Result<Package> {
+        let package_json = match find_package_json(path) {
+            Some(package_json) => package_json,
+            None => return Ok(Package::default()),
+        };
+        if let Some(package) = self.packages.lock().unwrap().get(&package_json) {
+            return Ok(package.clone())
+        }
+        // Another thread may read the same package.json meanwhile; the result is the same.
+        let package = read_package(&package_json, resolver)?;
+        self.packages.lock().unwrap().insert(package_json, package.clone());
+        Ok(package)
+    }
+}
+
+/// Find the package.json closest to a file.
+fn find_package_json(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .map(|dir| dir.join("package.json"))
+        .find(|package_json| package_json.is_file())
+}
+
+fn read_package(package_json: &Path, resolver: &Resolver) -> Result<Package> {
+    let file = File::open(package_json).map_err(|err| Error::io(package_json, err))?;
+    let package: Value = serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| Error::json(package_json, err))?;
+    Ok(Package {
+        transforms: read_transforms(package_json, &package, resolver)?,
+        is_module: package["type"] == "module",
+    })
+}
+
+fn read_transforms(package_json: &Path, package: &Value, resolver: &Resolver) -> Result<Transforms> {
+    let mut transforms = Transforms::new();
+    let entries = match package["browserify"]["transform"] {
+        Value::Null => return Ok(transforms),
+        Value::Array(ref entries) => entries.clone(),
+        ref entry => vec![entry.clone()],
+    };
+    let basedir = package_json.parent().unwrap().to_path_buf();
+    for entry in entries {
+        // Transforms are listed as "name" or ["name", { options }].
+        let (name, options) = match entry {
+            Value::String(name) => (name, Value::Null),
+            Value::Array(ref parts) if parts.len() <= 2 && parts.get(0).map_or(false, Value::is_string) =>
+                (parts[0].as_str().unwrap().to_string(), parts.get(1).cloned().unwrap_or(Value::Null)),
+            _ => return Err(Error::InvalidInput(format!("Invalid transform in {}: {}", package_json.to_string_lossy(), entry))),
+        };
+        let module = resolver.with_basedir(basedir.clone())
+            .resolve(&name)
+            .map_err(|err| Error::resolve(&name, Some(package_json), err))?;
+        let module = module.to_string_lossy().into_owned();
+        let transform = CommandTransform::new("node")
+            .with_name(&name)
+            .with_args(&["-e", NODE_TRANSFORM_RUNNER, module.as_str()]);
+        transforms.add(TransformRule::new(transform).with_options(options));
+    }
+    Ok(transforms)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use node_resolve::Resolver;
+    use deps::Deps;
+    use super::Packages;
+
+    #[test]
+    fn transforms_apply_only_to_their_package() {
+        let resolver = Resolver::new().with_extensions(&[".js", ".json"]);
+        let packages = Packages::new();
+
+        let index = Path::new("./tests/fixtures/package-transforms/index.js");
+        let transforms = packages.for_file(index, &resolver).unwrap().transforms;
+        assert_eq!(transforms.cache_key(index), r#"./upper:{"only":"strings"}"#);
+
+        let dep = Path::new("./tests/fixtures/package-transforms/node_modules/dep/index.js");
+        assert!(packages.for_file(dep, &resolver).unwrap().transforms.is_empty());
+    }
+
+    #[test]
+    fn runs_package_transforms() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/package-transforms/index.js").unwrap();
+        let source_of = |name: &str| deps.values()
+            .find(|record| record.file.path().ends_with(name))
+            .map(|record| record.file.source().clone())
+            .unwrap();
+        assert!(source_of("package-transforms/index.js").contains("console.log('HELLO', dep);"));
+        assert_eq!(source_of("dep/index.js"), "module.exports = 'world';\n");
+    }
+}
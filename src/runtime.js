@@ -1,6 +1,9 @@
 (function () {
-  function outer(modules, cache, entry) {
+  function outer(modules, cache, entry, chunks) {
     var previousRequire = typeof require == 'function' && require;
+    // Chunk IDs to callbacks waiting for that chunk, or `true` once it's loaded.
+    var loadedChunks = {};
+    chunks = chunks || {files:{},ensure:{}};
 
     function newRequire(name, jumped){
       if(!cache[name]) {
@@ -14,17 +17,53 @@
           throw err;
         }
         var m = cache[name] = {exports:{}};
-        modules[name][0].call(m.exports, function(x){
+        var localRequire = function(x){
           var id = modules[name][1][x];
           return newRequire(id ? id : x);
-        },m,m.exports,outer,modules,cache,entry);
+        };
+        localRequire.ensure = function(names, callback){
+          // Load every chunk that this module's split points need, including chunks with
+          // modules that are only required inside the callback.
+          loadChunks(chunks.ensure[name] || [], function(){ callback(localRequire); });
+        };
+        modules[name][0].call(m.exports,localRequire,m,m.exports,outer,modules,cache,entry);
       }
       return cache[name].exports;
     }
+
+    function loadChunks(ids, callback) {
+      // Start at 1 so `callback` is not called before all chunks were requested.
+      var pending = 1;
+      function done() { if (--pending === 0) callback(); }
+      for(var i=0;i<ids.length;i++) {
+        pending++;
+        loadChunk(ids[i], done);
+      }
+      done();
+    }
+
+    function loadChunk(chunk, callback) {
+      if (loadedChunks[chunk] === true) return callback();
+      if (loadedChunks[chunk]) return loadedChunks[chunk].push(callback);
+      loadedChunks[chunk] = [callback];
+      var script = document.createElement('script');
+      script.src = chunks.files[chunk];
+      script.async = true;
+      document.head.appendChild(script);
+    }
+
+    // Called by chunk files once they are loaded.
+    newRequire.chunk = function(chunk, newModules){
+      for(var id in newModules) modules[id] = newModules[id];
+      var callbacks = loadedChunks[chunk] || [];
+      loadedChunks[chunk] = true;
+      for(var i=0;i<callbacks.length;i++) callbacks[i]();
+    };
+
     for(var i=0;i<entry.length;i++) newRequire(entry[i]);
 
     return newRequire;
   }
 
   return outer;
-})()
+})()
\ No newline at end of file
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use serde_json::{self, Value};
+use estree_detect_requires::RequireContext;
+use error::{Error, Result};
+use graph::{Dependencies, Dependency, ModuleMap, ModuleRecord, SourceFile, hash_from_hex, hash_to_hex};
+
+/// Bump this when the serialized format changes.
+const GRAPH_VERSION: u32 = 3;
+
+/// Module IDs can have gaps after modules were removed, but `ModuleMap` allocates a slot for
+/// every ID up to the largest one, so IDs far larger than the number of modules are rejected.
+const MAX_IDS_PER_MODULE: usize = 16;
+const MIN_MAX_ID: usize = 1024;
+
+/// A serializable copy of a `ModuleMap`.
+///
+/// Syntax trees are not included, everything else round-trips losslessly.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedGraph {
+    version: u32,
+    /// Modules sorted by ID.
+    modules: Vec<SerializedModule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SerializedFile {
+    CJS {
+        dependencies: Vec<String>,
+        lazy_dependencies: Vec<String>,
+        /// Prefixes and suffixes of computed require() calls, and whether they are nested.
+        contexts: Vec<(String, String, bool)>,
+    },
+    JSON {
+        value: Value,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SerializedModule {
+    id: u32,
+    entry: bool,
+    path: PathBuf,
+    source: String,
+    /// Hex encoded hash of the source content.
+    hash: String,
+    file: SerializedFile,
+    dependencies: BTreeMap<String, SerializedDependency>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SerializedDependency {
+    name: String,
+    resolved: Option<PathBuf>,
+    id: Option<u32>,
+    lazy: bool,
+}
+
+impl SerializedGraph {
+    /// Copy a `ModuleMap` into its serializable form.
+    pub fn new(modules: &ModuleMap) -> SerializedGraph {
+        SerializedGraph {
+            version: GRAPH_VERSION,
+            modules: modules.values().map(serialize_module).collect(),
+        }
+    }
+
+    /// Rebuild a `ModuleMap`, with the same IDs and dependencies as the original.
+    pub fn into_module_map(self) -> Result<ModuleMap> {
+        if self.version != GRAPH_VERSION {
+            return Err(invalid(format!("Unsupported graph version {}, expected {}", self.version, GRAPH_VERSION)))
+        }
+
+        let count = self.modules.len();
+        let max_id = (count * MAX_IDS_PER_MODULE).max(MIN_MAX_ID);
+        let mut modules = ModuleMap::new();
+        for module in self.modules {
+            let id = module.id;
+            if id as usize > max_id {
+                return Err(invalid(format!("Module ID {} is too large for a graph with {} modules", id, count)))
+            }
+            if modules.insert(deserialize_module(module)?).is_some() {
+                return Err(invalid(format!("Duplicate module {}", id)))
+            }
+        }
+        Ok(modules)
+    }
+}
+
+/// Write a `ModuleMap` as JSON.
+pub fn write_graph<W: Write>(modules: &ModuleMap, writer: W) -> Result<()> {
+    serde_json::to_writer(writer, &SerializedGraph::new(modules))?;
+    Ok(())
+}
+
+/// Read a `ModuleMap` that was written by `write_graph`.
+pub fn read_graph<R: Read>(reader: R) -> Result<ModuleMap> {
+    let graph: SerializedGraph = serde_json::from_reader(reader)?;
+    graph.into_module_map()
+}
+
+fn serialize_module(record: &ModuleRecord) -> SerializedModule {
+    let file = match record.file {
+        SourceFile::CJS { ref dependencies, ref lazy_dependencies, ref contexts, .. } =>
+            SerializedFile::CJS {
+                dependencies: dependencies.clone(),
+                lazy_dependencies: lazy_dependencies.clone(),
+                contexts: contexts.iter()
+                    .map(|context| (context.prefix.clone(), context.suffix.clone(), context.nested))
+                    .collect(),
+            },
+        SourceFile::JSON { ref value, .. } => SerializedFile::JSON { value: value.clone() },
+    };
+    SerializedModule {
+        id: record.id,
+        entry: record.entry,
+        path: record.file.path().clone(),
+        source: record.file.source().clone(),
+        hash: hash_to_hex(record.file.hash()),
+        file,
+        dependencies: record.dependencies.iter()
+            .map(|(key, dependency)| (key.clone(), SerializedDependency {
+                name: dependency.name.clone(),
+                resolved: dependency.resolved.clone(),
+                id: dependency.id,
+                lazy: dependency.lazy,
+            }))
+            .collect(),
+    }
+}
+
+fn deserialize_module(module: SerializedModule) -> Result<ModuleRecord> {
+    let hash = hash_from_hex(&module.hash)
+        .ok_or_else(|| invalid(format!("Invalid hash for module {}", module.id)))?;
+    let file = match module.file {
+        SerializedFile::CJS { dependencies, lazy_dependencies, contexts } => SourceFile::CJS {
+            path: module.path,
+            source: module.source,
+            hash,
+            ast: None,
+            dependencies,
+            lazy_dependencies,
+            contexts: contexts.into_iter()
+                .map(|(prefix, suffix, nested)| RequireContext { prefix, suffix, nested })
+                .collect(),
+        },
+        SerializedFile::JSON { value } => SourceFile::JSON {
+            path: module.path,
+            source: module.source,
+            hash,
+            value,
+        },
+    };
+
+    let dependencies = module.dependencies.into_iter()
+        .map(|(key, serialized)| (key, Dependency {
+            name: serialized.name,
+            resolved: serialized.resolved,
+            id: serialized.id,
+            lazy: serialized.lazy,
+        }))
+        .collect::<Dependencies>();
+
+    Ok(ModuleRecord {
+        id: module.id,
+        entry: module.entry,
+        file,
+        dependencies,
+    })
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidInput(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::Deps;
+    use pack::Pack;
+    use super::{read_graph, write_graph};
+
+    #[test]
+    fn round_trips_module_map() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/cycle/a.js").unwrap();
+        deps.run("./tests/fixtures/module-exports/index.js").unwrap();
+
+        let mut json = vec![];
+        write_graph(&deps, &mut json).unwrap();
+        let modules = read_graph(&json[..]).unwrap();
+
+        let mut json_again = vec![];
+        write_graph(&modules, &mut json_again).unwrap();
+        assert_eq!(String::from_utf8(json).unwrap(), String::from_utf8(json_again).unwrap());
+        assert_eq!(Pack::new(&deps).to_string(), Pack::new(&modules).to_string());
+    }
+    #[test]
+    fn rejects_huge_module_ids() {
+        let json = r#"{"version":3,"modules":[{"id":4000000000,"entry":true,"path":"a.js","source":"",
+            "hash":"da39a3ee5e6b4b0d3255bfef95601890afd80709","file":{"type":"cjs","dependencies":[],
+            "lazy_dependencies":[],"contexts":[]},"dependencies":{}}]}"#;
+        assert!(read_graph(json.as_bytes()).is_err());
+        assert!(read_graph(json.replace("4000000000", "1").as_bytes()).is_ok());
+    }
+}
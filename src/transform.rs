@@ -0,0 +1,276 @@
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use glob::Pattern;
+use serde_json::Value;
+use error::{Error, Result};
+use events::{Event, Listeners};
+
+/// A source-to-source transform, applied to files before they are parsed.
+///
+/// Transforms are shared between the threads that load files, so they must be `Send + Sync`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate js_bundler;
+/// # extern crate serde_json;
+/// use std::path::Path;
+/// use js_bundler::Result;
+/// use js_bundler::transform::Transform;
+/// use serde_json::Value;
+///
+/// struct Strict;
+/// impl Transform for Strict {
+///     fn name(&self) -> &str {
+///         "strict"
+///     }
+///
+///     fn transform(&self, _path: &Path, source: String, _options: &Value) -> Result<String> {
+///         Ok(format!("'use strict';\n{}", source))
+///     }
+/// }
+/// # fn main() {}
+/// ```
+pub trait Transform: Send + Sync {
+    /// Name of the transform, for build events and error messages.
+    fn name(&self) -> &str;
+    /// Transform the source code of the file at `path`.
+    /// `options` are the options that the transform was registered with, or `Value::Null`.
+    fn transform(&self, path: &Path, source: String, options: &Value) -> Result<String>;
+}
+
+/// A transform that runs an external command.
+///
+/// The module source is written to the command's stdin, and its stdout becomes the new source.
+/// The path of the file being transformed is in the `BUNDLER_FILE` environment variable, and
+/// the transform options, if any, are in `BUNDLER_TRANSFORM_OPTIONS` as JSON.
+///
+/// # Examples
+///
+/// ```
+/// use js_bundler::transform::{CommandTransform, TransformRule};
+///
+/// let rule = TransformRule::new(CommandTransform::new("babel").with_args(&["--presets", "env"]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandTransform {
+    name: String,
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandTransform {
+    pub fn new(program: &str) -> CommandTransform {
+        CommandTransform {
+            name: program.to_string(),
+            program: program.to_string(),
+            args: vec![],
+        }
+    }
+
+    /// Use a different name in build events and error messages. Defaults to the program name.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Pass arguments to the command.
+    pub fn with_args<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+        self.args.extend(args.iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+}
+
+impl Transform for CommandTransform {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(&self, path: &Path, source: String, options: &Value) -> Result<String> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args)
+            .env("BUNDLER_FILE", path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if !options.is_null() {
+            command.env("BUNDLER_TRANSFORM_OPTIONS", options.to_string());
+        }
+        let mut child = command.spawn()
+            .map_err(|err| Error::transform(path, self.name(), format!("Could not run command: {}", err)))?;
+
+        // Write on a separate thread, so the command can't block on a full stdout pipe
+        // while we are still writing to its stdin.
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(source.as_bytes()));
+        let output = child.wait_with_output()
+            .map_err(|err| Error::transform(path, self.name(), format!("Could not run command: {}", err)))?;
+        // The command may exit without reading all of its input, so only report write errors
+        // if it also failed.
+        let written = writer.join().unwrap();
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = match written {
+                Err(err) => format!("{} (could not write source: {})", output.status, err),
+                Ok(()) => output.status.to_string(),
+            };
+            return Err(Error::transform(path, self.name(), format!("{}\n{}", message, stderr.trim_right())))
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| Error::transform(path, self.name(), "Command output is not valid UTF-8".into()))
+    }
+}
+
+/// A transform, the options to pass to it, and the files to apply it to.
+#[derive(Clone)]
+pub struct TransformRule {
+    transform: Arc<Transform>,
+    options: Value,
+    patterns: Vec<Pattern>,
+}
+
+impl TransformRule {
+    /// Apply `transform` to every file.
+    pub fn new<T: Transform + 'static>(transform: T) -> TransformRule {
+        TransformRule {
+            transform: Arc::new(transform),
+            options: Value::Null,
+            patterns: vec![],
+        }
+    }
+
+    /// Pass options to the transform.
+    pub fn with_options(mut self, options: Value) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Only apply the transform to files matching `pattern`, like `**/*.ts`.
+    /// If this is called more than once, files matching any of the patterns are transformed.
+    pub fn matching(mut self, pattern: Pattern) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Check if the transform should be applied to a file.
+    pub fn matches(&self, path: &Path) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|pattern| pattern.matches_path(path))
+    }
+
+    pub fn name(&self) -> &str {
+        self.transform.name()
+    }
+}
+
+impl fmt::Debug for TransformRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TransformRule")
+            .field("transform", &self.name())
+            .field("options", &self.options)
+            .field("patterns", &self.patterns)
+            .finish()
+    }
+}
+
+/// The transforms to apply to loaded files, in order.
+#[derive(Clone, Debug, Default)]
+pub struct Transforms {
+    rules: Vec<TransformRule>,
+}
+
+impl Transforms {
+    pub fn new() -> Transforms {
+        Transforms::default()
+    }
+
+    pub fn add(&mut self, rule: TransformRule) -> () {
+        self.rules.push(rule);
+    }
+
+    /// Add all rules from `transforms`, after the existing rules.
+    pub fn extend(&mut self, transforms: Transforms) -> () {
+        self.rules.extend(transforms.rules);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Run all matching transforms on the source code of a file.
+    pub fn apply(&self, path: &Path, source: String, listeners: &Listeners) -> Result<String> {
+        self.rules.iter()
+            .filter(|rule| rule.matches(path))
+            .fold(Ok(source), |res, rule| {
+                let source = rule.transform.transform(path, res?, &rule.options)?;
+                listeners.emit(Event::TransformApplied { path, transform: rule.name() });
+                Ok(source)
+            })
+    }
+
+    /// Describe the transforms that apply to a file, so cached files can be invalidated when
+    /// the transform configuration changes.
+    pub fn cache_key(&self, path: &Path) -> String {
+        self.rules.iter()
+            .filter(|rule| rule.matches(path))
+            .map(|rule| format!("{}:{}", rule.name(), rule.options))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use glob::Pattern;
+    use serde_json::{self, Value};
+    use deps::Deps;
+    use error::{Error, Result};
+    use super::{CommandTransform, Transform, TransformRule};
+
+    struct Prepend;
+    impl Transform for Prepend {
+        fn name(&self) -> &str {
+            "prepend"
+        }
+
+        fn transform(&self, _path: &Path, source: String, options: &Value) -> Result<String> {
+            Ok(format!("{}\n{}", options["line"].as_str().unwrap(), source))
+        }
+    }
+
+    #[test]
+    fn applies_to_matching_files() {
+        let rule = TransformRule::new(Prepend)
+            .with_options(serde_json::from_str(r#"{ "line": "// transformed" }"#).unwrap())
+            .matching(Pattern::new("**/double.js").unwrap());
+        let mut deps = Deps::new().with_transform(rule);
+        deps.run("./tests/fixtures/module-exports/index.js").unwrap();
+
+        for record in deps.values() {
+            let transformed = record.file.source().starts_with("// transformed\n");
+            let is_double = record.file.path().ends_with("double.js");
+            assert_eq!(transformed, is_double, "{}", record.file.path().to_string_lossy());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn command_errors_include_stderr() {
+        let transform = CommandTransform::new("sh")
+            .with_args(&["-c", "cat > /dev/null; echo \"cannot transform $BUNDLER_FILE\" >&2; exit 3"]);
+        match transform.transform(Path::new("a.js"), "module.exports = 1".into(), &Value::Null) {
+            Err(Error::Transform { ref message, .. }) => assert!(message.contains("cannot transform a.js"), "{}", message),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected an error"),
+        }
+
+        let transform = CommandTransform::new("tr").with_args(&["a-z", "A-Z"]);
+        let source = transform.transform(Path::new("a.js"), "abc".into(), &Value::Null).unwrap();
+        assert_eq!(source, "ABC");
+    }
+}
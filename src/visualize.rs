@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use graph::{ModuleMap, ModuleRecord, cycles, package_name};
+use pack::relative_path;
+
+/// Output format for the dependency graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    /// GraphViz DOT, see https://graphviz.org/doc/info/lang.html.
+    Dot,
+    /// Mermaid flowchart, see https://mermaid-js.github.io/.
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => Err(format!("Unknown graph format \"{}\", expected one of dot, mermaid", s)),
+        }
+    }
+}
+
+/// A node in the graph: a single module, or all modules in a package.
+#[derive(Default)]
+struct Node {
+    label: String,
+    /// Total source size in bytes.
+    size: usize,
+    /// Number of modules in this node.
+    modules: usize,
+    /// Whether this node is part of a cycle.
+    cyclic: bool,
+}
+
+/// An edge in the graph.
+#[derive(Default)]
+struct Edge {
+    /// The require() IDs that this edge represents.
+    names: BTreeSet<String>,
+    /// Whether this edge is part of a cycle.
+    cyclic: bool,
+}
+
+/// Write a `ModuleMap` as a graph, to see which modules end up in a bundle and why.
+pub struct Visualize<'a> {
+    modules: &'a ModuleMap,
+    format: GraphFormat,
+    root: PathBuf,
+    collapse_packages: bool,
+    highlight_cycles: bool,
+}
+
+impl<'a> Visualize<'a> {
+    pub fn new(modules: &ModuleMap) -> Visualize {
+        Visualize {
+            modules,
+            format: GraphFormat::Dot,
+            root: env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            collapse_packages: false,
+            highlight_cycles: false,
+        }
+    }
+
+    /// Set the output format. Defaults to `GraphFormat::Dot`.
+    pub fn with_format(mut self, format: GraphFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the project root directory. Module paths are shown relative to this directory.
+    /// Defaults to the current working directory.
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = root;
+        self
+    }
+
+    /// Show all modules from an npm package as a single node.
+    pub fn collapse_packages(mut self, collapse: bool) -> Self {
+        self.collapse_packages = collapse;
+        self
+    }
+
+    /// Color modules and dependencies that are part of a cycle.
+    pub fn highlight_cycles(mut self, highlight: bool) -> Self {
+        self.highlight_cycles = highlight;
+        self
+    }
+
+    pub fn to_string(&self) -> String {
+        let (nodes, edges) = self.collect();
+        let node_ids = nodes.keys()
+            .enumerate()
+            .map(|(index, key)| (key.clone(), format!("n{}", index)))
+            .collect::<HashMap<String, String>>();
+
+        match self.format {
+            GraphFormat::Dot => write_dot(&nodes, &edges, &node_ids),
+            GraphFormat::Mermaid => write_mermaid(&nodes, &edges, &node_ids),
+        }
+    }
+
+    /// Build the nodes and edges of the graph, keyed by node key.
+    fn collect(&self) -> (BTreeMap<String, Node>, BTreeMap<(String, String), Edge>) {
+        let cycle_of = if self.highlight_cycles { cycles(self.modules) } else { HashMap::new() };
+
+        let mut nodes = BTreeMap::new();
+        let mut edges = BTreeMap::new();
+        for record in self.modules.values() {
+            let key = self.node_key(record);
+            let node = nodes.entry(key.clone()).or_insert_with(Node::default);
+            node.label = self.node_label(record);
+            node.size += record.file.source().len();
+            node.modules += 1;
+            node.cyclic = node.cyclic || cycle_of.contains_key(&record.id);
+
+            for dependency in record.dependencies.values() {
+                let dep_record = match dependency.id.and_then(|id| self.modules.get(id)) {
+                    Some(dep_record) => dep_record,
+                    None => continue,
+                };
+                let dep_key = self.node_key(dep_record);
+                // Don't draw dependencies inside a collapsed package.
+                if dep_key == key && dep_record.id != record.id {
+                    continue
+                }
+                let edge = edges.entry((key.clone(), dep_key)).or_insert_with(Edge::default);
+                edge.names.insert(dependency.name.clone());
+                edge.cyclic = edge.cyclic || match (cycle_of.get(&record.id), cycle_of.get(&dep_record.id)) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => false,
+                };
+            }
+        }
+        (nodes, edges)
+    }
+
+    fn node_key(&self, record: &ModuleRecord) -> String {
+        match self.package(record) {
+            Some(name) => format!("package:{}", name),
+            None => format!("module:{}", record.id),
+        }
+    }
+
+    fn node_label(&self, record: &ModuleRecord) -> String {
+        match self.package(record) {
+            Some(name) => name,
+            None => relative_path(&self.root, record.file.path()),
+        }
+    }
+
+    /// Get the package that a module should be collapsed into, if any.
+    fn package(&self, record: &ModuleRecord) -> Option<String> {
+        if self.collapse_packages {
+            package_name(record.file.path())
+        } else {
+            None
+        }
+    }
+}
+
+fn write_dot(nodes: &BTreeMap<String, Node>, edges: &BTreeMap<(String, String), Edge>, node_ids: &HashMap<String, String>) -> String {
+    let mut string = String::from("digraph modules {\n  rankdir=LR;\n  node [shape=box];\n");
+    for (key, node) in nodes {
+        string.push_str(&format!("  {} [label=\"{}\\n{}\"{}];\n",
+                                 node_ids[key],
+                                 escape_dot(&node.label),
+                                 describe_size(node),
+                                 if node.cyclic { ", color=red" } else { "" }));
+    }
+    for (&(ref from, ref to), edge) in edges {
+        string.push_str(&format!("  {} -> {} [label=\"{}\"{}];\n",
+                                 node_ids[from],
+                                 node_ids[to],
+                                 escape_dot(&edge_label(edge)),
+                                 if edge.cyclic { ", color=red" } else { "" }));
+    }
+    string.push_str("}\n");
+    string
+}
+
+fn write_mermaid(nodes: &BTreeMap<String, Node>, edges: &BTreeMap<(String, String), Edge>, node_ids: &HashMap<String, String>) -> String {
+    let mut string = String::from("graph LR\n");
+    for (key, node) in nodes {
+        string.push_str(&format!("  {}[\"{}<br/>{}\"]\n",
+                                 node_ids[key],
+                                 escape_mermaid(&node.label),
+                                 describe_size(node)));
+    }
+    let mut cyclic_links = vec![];
+    for (index, (&(ref from, ref to), edge)) in edges.iter().enumerate() {
+        string.push_str(&format!("  {} -->|\"{}\"| {}\n",
+                                 node_ids[from],
+                                 escape_mermaid(&edge_label(edge)),
+                                 node_ids[to]));
+        if edge.cyclic {
+            cyclic_links.push(index.to_string());
+        }
+    }
+
+    let cyclic_nodes = nodes.iter()
+        .filter(|&(_, node)| node.cyclic)
+        .map(|(key, _)| node_ids[key].clone())
+        .collect::<Vec<String>>();
+    if !cyclic_nodes.is_empty() {
+        string.push_str("  classDef cycle stroke:#d00,stroke-width:2px\n");
+        string.push_str(&format!("  class {} cycle\n", cyclic_nodes.join(",")));
+    }
+    if !cyclic_links.is_empty() {
+        string.push_str(&format!("  linkStyle {} stroke:#d00\n", cyclic_links.join(",")));
+    }
+    string
+}
+
+fn edge_label(edge: &Edge) -> String {
+    edge.names.iter().cloned().collect::<Vec<String>>().join(", ")
+}
+
+fn describe_size(node: &Node) -> String {
+    let size = if node.size < 1024 {
+        format!("{} B", node.size)
+    } else {
+        format!("{:.1} kB", node.size as f64 / 1024.0)
+    };
+    if node.modules > 1 {
+        format!("{} modules, {}", node.modules, size)
+    } else {
+        size
+    }
+}
+
+fn escape_dot(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(string: &str) -> String {
+    string.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use deps::Deps;
+    use super::{GraphFormat, Visualize};
+
+    #[test]
+    fn highlights_cycles() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/cycle/a.js").unwrap();
+
+        let dot = Visualize::new(&deps).highlight_cycles(true).to_string();
+        assert!(dot.contains("  n0 [label=\"tests/fixtures/cycle/a.js\\n"));
+        assert!(dot.contains("  n0 -> n1 [label=\"./b\", color=red];\n"));
+        assert!(dot.contains("  n1 -> n0 [label=\"./a\", color=red];\n"));
+        assert!(!Visualize::new(&deps).to_string().contains("color=red"));
+
+        let mermaid = Visualize::new(&deps).with_format(GraphFormat::Mermaid).highlight_cycles(true).to_string();
+        assert!(mermaid.contains("  n0 -->|\"./b\"| n1\n"));
+        assert!(mermaid.contains("  class n0,n1 cycle\n"));
+        assert!(mermaid.contains("  linkStyle 0,1 stroke:#d00\n"));
+    }
+
+    #[test]
+    fn collapses_packages() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/vendor/index.js").unwrap();
+
+        let dot = Visualize::new(&deps).collapse_packages(true).to_string();
+        assert!(dot.contains("  n2 [label=\"dep\\n"));
+        assert!(dot.contains("  n0 -> n2 [label=\"dep\"];\n"));
+        assert!(!dot.contains("node_modules"));
+
+        let dot = Visualize::new(&deps).to_string();
+        assert!(dot.contains("tests/fixtures/vendor/node_modules/dep/index.js"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::canonicalize;
+use std::path::Path;
+use graph::{ModuleMap, ModuleRecord, package_name};
+use pack::relative_path;
+
+/// A chain of require() calls from an entry point to a module.
+#[derive(Debug)]
+pub struct RequireChain {
+    /// ID of the entry point module.
+    pub entry: u32,
+    /// Pairs of the ID passed to require() and the ID of the module it resolved to,
+    /// in the order they are required starting from the entry point.
+    pub steps: Vec<(String, u32)>,
+}
+
+/// Find out why a module ends up in a bundle.
+///
+/// `target` is a file path, or the name of an npm package. For every entry point that
+/// includes a matching module, this returns the shortest chain of require() calls that leads
+/// to it. Entry points are sorted by ID, and ties are broken by the order of require() IDs,
+/// so the result is the same for every run.
+pub fn why_included(modules: &ModuleMap, target: &str) -> Vec<RequireChain> {
+    let target_path = canonicalize(target).ok();
+    let matches = |record: &ModuleRecord| {
+        package_name(record.file.path()).map_or(false, |name| name == target) ||
+            record.file.path() == Path::new(target) ||
+            target_path.as_ref().map_or(false, |target_path| {
+                canonicalize(record.file.path()).ok().as_ref() == Some(target_path)
+            })
+    };
+    let mut targets = modules.values()
+        .filter(|record| matches(*record))
+        .map(|record| record.id)
+        .collect::<Vec<u32>>();
+    targets.sort();
+
+    // Walk the graph backwards from the targets, so every module learns which of its
+    // dependencies is the first step on the shortest chain to a target.
+    let mut next: HashMap<u32, Option<(String, u32)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for &id in &targets {
+        next.insert(id, None);
+        queue.push_back(id);
+    }
+    while let Some(id) = queue.pop_front() {
+        for dependent in modules.dependents(id) {
+            if next.contains_key(&dependent.id) {
+                continue
+            }
+            let name = dependent.dependencies.values()
+                .find(|dependency| dependency.id == Some(id))
+                .map(|dependency| dependency.name.clone())
+                .unwrap();
+            next.insert(dependent.id, Some((name, id)));
+            queue.push_back(dependent.id);
+        }
+    }
+
+    let mut entries = modules.values()
+        .filter(|record| record.entry && next.contains_key(&record.id))
+        .map(|record| record.id)
+        .collect::<Vec<u32>>();
+    entries.sort();
+    entries.into_iter()
+        .map(|entry| {
+            let mut steps = vec![];
+            let mut current = entry;
+            while let Some((name, id)) = next[&current].clone() {
+                steps.push((name, id));
+                current = id;
+            }
+            RequireChain { entry, steps }
+        })
+        .collect()
+}
+
+/// Describe require chains in a human readable way, one module per line.
+///
+/// ```text
+/// src/app.js
+///   require('./lib') -> src/lib.js
+///   require('lodash') -> node_modules/lodash/index.js
+/// ```
+pub fn describe_chains(modules: &ModuleMap, chains: &[RequireChain], root: &Path) -> String {
+    let path_of = |id: u32| modules.get(id)
+        .map_or_else(|| format!("<module {}>", id), |record| relative_path(root, record.file.path()));
+
+    let mut string = String::new();
+    for chain in chains {
+        if !string.is_empty() {
+            string.push('\n');
+        }
+        string.push_str(&path_of(chain.entry));
+        string.push('\n');
+        for &(ref name, id) in &chain.steps {
+            string.push_str(&format!("  require('{}') -> {}\n", name, path_of(id)));
+        }
+    }
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use deps::Deps;
+    use super::{describe_chains, why_included};
+
+    #[test]
+    fn finds_shortest_require_chains() {
+        let mut deps = Deps::new();
+        deps.run("./tests/fixtures/ensure/index.js").unwrap();
+        let chains = why_included(&deps, "./tests/fixtures/ensure/shared.js");
+        assert_eq!(chains.len(), 1);
+        let names = chains[0].steps.iter()
+            .map(|&(ref name, _)| name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["./one", "./y", "./shared"]);
+        assert_eq!(describe_chains(&deps, &chains, &env::current_dir().unwrap()),
+                   "tests/fixtures/ensure/index.js\n  require('./one') -> tests/fixtures/ensure/one.js\n  \
+                    require('./y') -> tests/fixtures/ensure/y.js\n  require('./shared') -> tests/fixtures/ensure/shared.js\n");
+
+        assert!(why_included(&deps, "./tests/fixtures/cycle/a.js").is_empty());
+    }
+}
@@ -0,0 +1,3 @@
+['one', 'two'].forEach(function (name) {
+  console.log(require('./' + name));
+});
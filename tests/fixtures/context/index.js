@@ -0,0 +1,4 @@
+var pages = [['x', 'one'], ['y', 'two']];
+pages.forEach(function (page) {
+  console.log(require('./a/' + page[0] + '/' + page[1] + '.js'));
+});
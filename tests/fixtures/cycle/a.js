@@ -0,0 +1,4 @@
+exports.early = 'a';
+var b = require('./b');
+exports.late = 'a';
+console.log(JSON.stringify({ a: require('./b'), b: b }));
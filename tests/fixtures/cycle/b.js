@@ -0,0 +1,3 @@
+var a = require('./a');
+exports.sawEarly = String(a.early);
+exports.sawLate = String(a.late);
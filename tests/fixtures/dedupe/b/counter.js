@@ -0,0 +1,4 @@
+var count = 0;
+exports.name = require('./name');
+exports.increment = function () { count++; };
+exports.count = function () { return count; };
@@ -0,0 +1,4 @@
+var a = require('./a/counter');
+var b = require('./b/counter');
+a.increment();
+console.log(a.name, a.count(), b.name, b.count());
@@ -0,0 +1,5 @@
+// Nothing is listed in the first argument, so the chunk for './y' is only known from the
+// require() call inside the callback.
+require.ensure([], function (require) {
+  console.log(require('./y'));
+});
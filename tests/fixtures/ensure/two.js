@@ -0,0 +1,6 @@
+// Never called, but './x' gets the first chunk, together with './shared'.
+module.exports = function () {
+  require.ensure([], function (require) {
+    console.log(require('./x'));
+  });
+};
@@ -0,0 +1 @@
+module.exports = 'x ' + require('./shared');
@@ -0,0 +1 @@
+module.exports = 'y ' + require('./shared');
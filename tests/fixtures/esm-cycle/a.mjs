@@ -0,0 +1,4 @@
+import { b } from './b.mjs';
+export function a() { return 'a'; }
+export var value = 'value';
+console.log(b());
@@ -0,0 +1,2 @@
+import { a, value } from './a.mjs';
+export function b() { return a() + ' ' + value; }
@@ -0,0 +1 @@
+require('./does-not-exist');
@@ -0,0 +1,3 @@
+var double = require('./double');
+var data = require('./data.json');
+console.log(double(data.value));
@@ -0,0 +1,2 @@
+var dep = require("dep");
+console.log('hello', dep);
@@ -0,0 +1,9 @@
+var Transform = require('stream').Transform;
+
+module.exports = function (file, opts) {
+  return new Transform({
+    transform: function (chunk, enc, next) {
+      next(null, chunk.toString().replace(/'[^']*'/g, function (str) { return str.toUpperCase(); }));
+    }
+  });
+};